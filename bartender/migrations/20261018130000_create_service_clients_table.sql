@@ -0,0 +1,8 @@
+CREATE TABLE service_clients
+(
+    client_id   TEXT PRIMARY KEY,
+    name        TEXT        NOT NULL,
+    secret_hash TEXT        NOT NULL,
+    scopes      TEXT[]      NOT NULL DEFAULT '{}',
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
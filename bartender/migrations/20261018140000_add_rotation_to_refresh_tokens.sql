@@ -0,0 +1,21 @@
+ALTER TABLE refresh_tokens
+    RENAME COLUMN refresh_token TO token_hash;
+ALTER TABLE refresh_tokens
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ,
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ,
+    ADD COLUMN family_id  UUID,
+    ADD COLUMN used_at    TIMESTAMPTZ,
+    ADD COLUMN revoked_at TIMESTAMPTZ;
+
+-- Tokens stored before rotation are kept: each becomes a family of its own, stored by
+-- hash like new tokens. Tokens without a user were never usable.
+DELETE FROM refresh_tokens WHERE user_id IS NULL;
+UPDATE refresh_tokens
+SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex'),
+    family_id  = gen_random_uuid();
+
+ALTER TABLE refresh_tokens
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN family_id SET NOT NULL;
+CREATE INDEX refresh_tokens_family_id_idx ON refresh_tokens (family_id);
+CREATE INDEX refresh_tokens_expires_at_idx ON refresh_tokens (expires_at);
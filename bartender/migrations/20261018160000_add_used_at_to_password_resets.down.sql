@@ -0,0 +1,8 @@
+DROP INDEX IF EXISTS password_resets_user_id_idx;
+ALTER TABLE password_resets
+    DROP COLUMN IF EXISTS used_at,
+    ALTER COLUMN created_at TYPE TIMESTAMP,
+    ALTER COLUMN expires_at TYPE TIMESTAMP,
+    ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE password_resets
+    RENAME COLUMN token_hash TO reset_token;
@@ -0,0 +1,15 @@
+ALTER TABLE password_resets
+    RENAME COLUMN reset_token TO token_hash;
+
+-- Tokens stored before are kept, stored by hash like new tokens. Tokens without a user
+-- were never usable.
+DELETE FROM password_resets WHERE user_id IS NULL;
+UPDATE password_resets
+SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
+
+ALTER TABLE password_resets
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ,
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ,
+    ADD COLUMN used_at TIMESTAMPTZ;
+CREATE INDEX password_resets_user_id_idx ON password_resets (user_id);
@@ -0,0 +1,14 @@
+ALTER TABLE users
+    ADD COLUMN email_verified_at TIMESTAMPTZ;
+-- Accounts created before verification existed keep working whatever the policy
+UPDATE users SET email_verified_at = NOW();
+
+CREATE TABLE email_verifications
+(
+    id         UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    user_id    UUID        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX email_verifications_user_id_idx ON email_verifications (user_id);
@@ -0,0 +1,3 @@
+ALTER TABLE users
+    ALTER COLUMN username TYPE TEXT,
+    ALTER COLUMN email TYPE TEXT;
@@ -0,0 +1,6 @@
+-- Fails if two users differ only in the case of their username or email: merge them first
+CREATE EXTENSION IF NOT EXISTS citext;
+
+ALTER TABLE users
+    ALTER COLUMN username TYPE CITEXT,
+    ALTER COLUMN email TYPE CITEXT;
@@ -0,0 +1,10 @@
+-- Failed logins per client IP and per account, for backoff and lockout
+CREATE TABLE login_attempts
+(
+    scope          TEXT        NOT NULL CHECK (scope IN ('ip', 'account')),
+    key            TEXT        NOT NULL,
+    failures       INTEGER     NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_until   TIMESTAMPTZ,
+    PRIMARY KEY (scope, key)
+);
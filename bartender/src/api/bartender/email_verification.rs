@@ -0,0 +1,117 @@
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::{internal_error, random_token, validate_payload};
+use crate::api::payload::{ResendVerificationPayload, VerifyEmailPayload};
+use crate::app::AppState;
+use crate::mailer::Email;
+use auth::hashing::hash_token;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::{Duration, Utc};
+use log::error;
+use models::user::User;
+use repository::auth::AuthRepositoryError;
+use std::sync::Arc;
+
+/// Issues a verification token for the email of `user` and mails it in the background.
+pub async fn send_verification_email(
+    state: &Arc<AppState>,
+    user: &User,
+) -> Result<(), sqlx::Error> {
+    let token = random_token();
+    let expiration = state.email_verification.token_expiration;
+    state
+        .email_verification_repository
+        .create(
+            user.id,
+            &hash_token(&token),
+            Utc::now() + Duration::seconds(expiration as i64),
+        )
+        .await?;
+
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Verify your email".to_string(),
+        body: format!(
+            "Use this token to verify the email of {}:\r\n\r\n{}\r\n\r\n\
+             It expires in {} hours.",
+            user.username,
+            token,
+            expiration / 3600
+        ),
+    };
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(err) = mailer.send(&email).await {
+            error!("Failed to send verification email: {}", err);
+        }
+    });
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify-email",
+    request_body = VerifyEmailPayload,
+    responses(
+        (status = 204, description = "Email verified, log in again to get all scopes"),
+        (status = 400, description = "Validation failed, or invalid or expired token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn verify_email(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<VerifyEmailPayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+
+    match state
+        .email_verification_repository
+        .verify(&hash_token(&payload.token))
+        .await
+    {
+        Ok(Some(_)) => Ok(StatusCode::NO_CONTENT),
+        Ok(None) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                message: "Invalid or expired verification token".to_string(),
+                details: None,
+            }),
+        )),
+        Err(_) => Err(internal_error()),
+    }
+}
+
+/// Mails a new verification token, e.g. when the first one expired. Like the password reset,
+/// the response doesn't tell whether the email is registered.
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify-email/resend",
+    request_body = ResendVerificationPayload,
+    responses(
+        (status = 202, description = "Token emailed if an unverified user has this email"),
+        (status = 400, description = "Validation failed", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn resend_verification_email(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<ResendVerificationPayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+
+    let user = match state.auth_repository.find_by_email(&payload.email).await {
+        Ok(user) => User::from(user),
+        Err(AuthRepositoryError::UserNotFound) => return Ok(StatusCode::ACCEPTED),
+        Err(_) => return Err(internal_error()),
+    };
+    match state.auth_repository.is_email_verified(user.id).await {
+        Ok(true) => return Ok(StatusCode::ACCEPTED),
+        Ok(false) => {}
+        Err(_) => return Err(internal_error()),
+    }
+
+    send_verification_email(&state, &user)
+        .await
+        .map_err(|_| internal_error())?;
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,305 @@
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::{authenticate_client, invalid_client};
+use crate::api::payload::IntrospectPayload;
+use crate::app::AppState;
+use auth::claims::{Claims, Principal, TokenUse};
+use auth::personal_tokens::{is_personal_token, PersonalToken};
+use auth::tokens::TokenManager;
+use axum::http::StatusCode;
+use axum::{Extension, Form, Json};
+use axum_extra::TypedHeader;
+use headers::authorization::Basic;
+use headers::Authorization;
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// RFC 7662 introspection response. Only `active` is set for invalid tokens.
+#[derive(Serialize, ToSchema)]
+pub struct IntrospectResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_use: Option<String>,
+    /// `user` or `service`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub principal: Option<Principal>,
+    /// Login session of the token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+impl IntrospectResponse {
+    fn inactive() -> Self {
+        Self {
+            active: false,
+            sub: None,
+            exp: None,
+            iat: None,
+            iss: None,
+            aud: None,
+            jti: None,
+            scope: None,
+            roles: None,
+            token_use: None,
+            principal: None,
+            sid: None,
+        }
+    }
+}
+
+impl From<Claims> for IntrospectResponse {
+    fn from(claims: Claims) -> Self {
+        let token_use = match claims.token_use {
+            TokenUse::Access => "access",
+            TokenUse::Refresh => "refresh",
+        };
+        Self {
+            active: true,
+            sub: Some(claims.sub),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            iss: Some(claims.iss),
+            aud: Some(claims.aud),
+            jti: Some(claims.jti),
+            scope: Some(claims.scope),
+            roles: Some(claims.roles),
+            token_use: Some(token_use.to_string()),
+            principal: Some(claims.principal),
+            sid: claims.sid,
+        }
+    }
+}
+
+impl IntrospectResponse {
+    /// Personal access tokens carry no `iss` and `aud`, they are valid wherever JWTs are.
+    fn from_personal_token(personal_token: PersonalToken, token_manager: &TokenManager) -> Self {
+        Self {
+            active: true,
+            sub: Some(personal_token.user_id),
+            exp: personal_token
+                .expires_at
+                .map(|expires_at| expires_at.timestamp() as usize),
+            scope: Some(personal_token.scopes.join(" ")),
+            roles: Some(personal_token.roles),
+            token_use: Some("access".to_string()),
+            principal: Some(Principal::User),
+            iss: Some(token_manager.issuer().to_string()),
+            aud: Some(token_manager.audience().to_vec()),
+            ..Self::inactive()
+        }
+    }
+}
+
+/// Token introspection (RFC 7662) for services that validate tokens remotely instead of
+/// holding the verification key. Callers authenticate as a service client with HTTP Basic.
+#[utoipa::path(
+    post,
+    path = "/api/auth/introspect",
+    request_body(content = IntrospectPayload, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token state, `active` is false for invalid, expired or revoked tokens and disabled users", body = IntrospectResponse),
+        (status = 401, description = "Invalid client credentials", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn introspect(
+    Extension(state): Extension<Arc<AppState>>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(payload): Form<IntrospectPayload>,
+) -> Result<Json<IntrospectResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(TypedHeader(Authorization(basic))) = basic else {
+        return Err(invalid_client());
+    };
+    authenticate_client(&state, basic.username(), basic.password()).await?;
+
+    Ok(Json(introspect_token(&state, &payload.token).await))
+}
+
+async fn introspect_token(state: &Arc<AppState>, token: &str) -> IntrospectResponse {
+    if is_personal_token(token) {
+        return introspect_personal_token(state, token).await;
+    }
+
+    let claims = match state
+        .token_manager
+        .validate_token(token, TokenUse::Access)
+        .or_else(|_| state.token_manager.validate_token(token, TokenUse::Refresh))
+    {
+        Ok(claims) => claims,
+        Err(_) => return IntrospectResponse::inactive(),
+    };
+
+    // Tokens are reported inactive when revocation or the user can't be checked
+    if !matches!(state.token_manager.is_revoked(&claims).await, Ok(false)) {
+        return IntrospectResponse::inactive();
+    }
+    if claims.principal == Principal::Service {
+        return IntrospectResponse::from(claims);
+    }
+    match state.token_manager.is_user_active(&claims.sub).await {
+        Ok(true) => IntrospectResponse::from(claims),
+        _ => IntrospectResponse::inactive(),
+    }
+}
+
+/// Personal access tokens have no `exp` unless created with an expiration.
+async fn introspect_personal_token(state: &Arc<AppState>, token: &str) -> IntrospectResponse {
+    let personal_token = match state.token_manager.find_personal_token(token).await {
+        Ok(Some(personal_token)) => personal_token,
+        _ => return IntrospectResponse::inactive(),
+    };
+
+    match state
+        .token_manager
+        .is_user_active(&personal_token.user_id)
+        .await
+    {
+        Ok(true) => IntrospectResponse::from_personal_token(personal_token, &state.token_manager),
+        _ => IntrospectResponse::inactive(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use auth::revocation::InMemoryRevocationStore;
+    use auth::test_util::test_token_manager;
+    use auth::tokens::TokenManager;
+    use auth::users::{UserLookup, UserLookupError};
+    use chrono::Duration;
+    use models::user::User;
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    /// Every user is disabled.
+    struct NoActiveUsers;
+
+    #[async_trait]
+    impl UserLookup for NoActiveUsers {
+        async fn is_active(&self, _user_id: &str) -> Result<bool, UserLookupError> {
+            Ok(false)
+        }
+    }
+
+    fn state() -> Arc<AppState> {
+        state_with(|token_manager| token_manager)
+    }
+
+    fn state_with(configure: impl FnOnce(TokenManager) -> TokenManager) -> Arc<AppState> {
+        let token_manager = test_token_manager().with_revocation_store(
+            Arc::new(InMemoryRevocationStore::new()),
+            std::time::Duration::from_secs(60),
+        );
+        let pool = PgPool::connect_lazy("postgres://localhost/bartender").unwrap();
+        Arc::new(AppState::new(pool, configure(token_manager), vec![]))
+    }
+
+    async fn call(state: &Arc<AppState>, token: &str) -> IntrospectResponse {
+        introspect_token(state, token).await
+    }
+
+    fn alice() -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: String::new(),
+            roles: vec!["user".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_active_token() {
+        let state = state();
+        let user = alice();
+        let token = state
+            .token_manager
+            .generate_access_token(&user, &["tasks:read".to_string()], Duration::seconds(60))
+            .unwrap();
+
+        let response = call(&state, &token).await;
+        assert!(response.active);
+        assert_eq!(response.sub, Some(user.id.to_string()));
+        assert_eq!(response.scope.as_deref(), Some("tasks:read"));
+        assert_eq!(response.token_use.as_deref(), Some("access"));
+
+        let claims = state
+            .token_manager
+            .validate_token(&token, TokenUse::Access)
+            .unwrap();
+        state.token_manager.revoke(&claims).await.unwrap();
+        assert!(!call(&state, &token).await.active);
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_are_required() {
+        let result = introspect(
+            Extension(state()),
+            None,
+            Form(IntrospectPayload {
+                token: "not a token".to_string(),
+            }),
+        )
+        .await;
+
+        let (status, _) = result.err().unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_inactive() {
+        let response = call(&state(), "not a token").await;
+        assert!(!response.active);
+        assert!(response.sub.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_service_token_skips_user_lookup() {
+        let state =
+            state_with(|token_manager| {
+                token_manager
+                    .with_user_lookup(Arc::new(NoActiveUsers), std::time::Duration::from_secs(60))
+            });
+        let token = state
+            .token_manager
+            .generate_service_token("billing", &[], Duration::seconds(60))
+            .unwrap();
+
+        let response = call(&state, &token).await;
+        assert!(response.active);
+        assert_eq!(response.principal, Some(Principal::Service));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_user_is_inactive() {
+        let state =
+            state_with(|token_manager| {
+                token_manager
+                    .with_user_lookup(Arc::new(NoActiveUsers), std::time::Duration::from_secs(60))
+            });
+        let token = state
+            .token_manager
+            .generate_access_token(&alice(), &[], Duration::seconds(60))
+            .unwrap();
+
+        assert!(!call(&state, &token).await.active);
+    }
+}
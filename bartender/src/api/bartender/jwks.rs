@@ -0,0 +1,15 @@
+use crate::app::AppState;
+use axum::{Extension, Json};
+use jsonwebtoken::jwk::JwkSet;
+use std::sync::Arc;
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "Public keys used to verify issued tokens", content_type = "application/json"),
+    )
+)]
+pub async fn jwks(Extension(state): Extension<Arc<AppState>>) -> Json<JwkSet> {
+    Json(state.token_manager.jwks())
+}
@@ -1,56 +1,161 @@
-use crate::api::entities::{AccessTokens, ErrorResponse};
-use crate::api::helpers::{generate_tokens, validate_payload};
+use crate::api::bartender::mfa::create_mfa_challenge;
+use crate::api::client::ClientInfo;
+use crate::api::cookies::set_session_cookies;
+use crate::api::entities::{ErrorResponse, LoginResponse};
+use crate::api::helpers::{
+    apply_email_verification_policy, ensure_not_disabled, internal_error, narrow_scopes,
+    start_session, upgrade_password_hash, validate_payload, verify_password,
+};
 use crate::api::payload::LoginPayload;
+use crate::api::throttle::{clear_failed_logins, release_attempt, reserve_attempt, LoginAttempt};
 use crate::app::AppState;
 use axum::http::StatusCode;
 use axum::{Extension, Json};
+use axum_extra::extract::CookieJar;
 use models::user::User;
+use repository::auth::AuthRepositoryError;
 use std::sync::Arc;
 
+/// Failed attempts are throttled per client IP and per account with exponential backoff,
+/// see `login_throttle`. Users with two-factor authentication get a challenge to pass with
+/// `verify_mfa` instead of the tokens.
 #[utoipa::path(
     post,
     path = "/api/auth/login",
     request_body = LoginPayload,
     responses(
-        (status = 200, description = "Successful login", body = AccessTokens),
-        (status = 401, description = "Invalid username or password", body = ErrorResponse),
+        (status = 200, description = "Successful login, also sets session cookies when enabled. With two-factor authentication a challenge for `verify_mfa` instead", body = LoginResponse),
+        (status = 400, description = "Validation failed or unknown scope requested", body = ErrorResponse),
+        (status = 401, description = "Invalid login or password", body = ErrorResponse),
+        (status = 403, description = "User is disabled or email is not verified", body = ErrorResponse),
+        (status = 429, description = "Too many failed attempts from the client or for the account, `retry_after` seconds in details", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     )
 )]
 pub async fn login(
     Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    jar: CookieJar,
     Json(payload): Json<LoginPayload>,
-) -> Result<Json<AccessTokens>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(CookieJar, Json<LoginResponse>), (StatusCode, Json<ErrorResponse>)> {
     validate_payload(&payload)?;
+    let scopes = narrow_scopes(&state.scopes, payload.scope.as_deref())?;
 
-    let user = match state
-        .auth_repository
-        .find_by_username(&payload.username)
-        .await
-    {
-        Ok(user) => User::from(user),
-        Err(_) => {
+    let user = match state.auth_repository.find_by_login(&payload.login).await {
+        Ok(user) => Some(User::from(user)),
+        Err(AuthRepositoryError::UserNotFound) => None,
+        Err(_) => return Err(internal_error()),
+    };
+    let user_id = user.as_ref().map(|user| user.id.to_string());
+    let attempt = LoginAttempt::new(
+        client.ip_address.as_deref(),
+        &payload.login,
+        user_id.as_deref(),
+    );
+    let attempt = reserve_attempt(&state, &attempt).await?;
+
+    let password_hash = user.as_ref().map(|user| user.password_hash.as_str());
+    let verified = verify_password(&state, &payload.password, password_hash).await;
+    let user = match user {
+        Some(user) if verified => user,
+        _ => {
             return Err((
                 StatusCode::UNAUTHORIZED,
                 Json(ErrorResponse {
-                    message: "Invalid username or password".to_string(),
+                    message: "Invalid login or password".to_string(),
                     details: None,
                 }),
             ));
         }
     };
+    upgrade_password_hash(&state, &user, &payload.password).await;
+
+    // The session starts once the second factor is verified by `verify_mfa`, the failures
+    // of the account are cleared only then
+    let mfa_enabled = state
+        .totp_repository
+        .is_enabled(user.id)
+        .await
+        .map_err(|_| internal_error())?;
+    if mfa_enabled {
+        release_attempt(&state, attempt).await;
+    } else {
+        clear_failed_logins(&state, attempt).await;
+    }
+
+    ensure_not_disabled(&state, &user).await?;
+    let scopes = apply_email_verification_policy(&state, &user, scopes).await?;
+    if mfa_enabled {
+        let challenge = create_mfa_challenge(&state, &user, &scopes).await?;
+        return Ok((jar, Json(LoginResponse::MfaRequired(challenge))));
+    }
 
-    if !bcrypt::verify(&payload.password, &user.password_hash).unwrap_or(false) {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                message: "Invalid username or password".to_string(),
-                details: None,
+    let tokens = start_session(&state, &client, &user, &scopes).await?;
+
+    let jar = set_session_cookies(&state, jar, &tokens);
+
+    Ok((jar, Json(LoginResponse::Tokens(tokens))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{create_test_user, test_client, test_state, TEST_PASSWORD};
+    use models::user::{UserModel, ROLE_USER};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    async fn log_in(
+        state: &Arc<AppState>,
+        login_name: &str,
+    ) -> Result<LoginResponse, (StatusCode, Json<ErrorResponse>)> {
+        login(
+            Extension(state.clone()),
+            test_client(),
+            CookieJar::new(),
+            Json(LoginPayload {
+                login: login_name.to_string(),
+                password: TEST_PASSWORD.to_string(),
+                scope: None,
             }),
-        ));
+        )
+        .await
+        .map(|(_, Json(response))| response)
     }
 
-    let tokens = generate_tokens(&state, &user)?;
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_login_by_username_or_email(pool: PgPool) {
+        let state = test_state(pool);
+        create_test_user(&state, "alice").await;
+        // Registered before usernames were validated
+        let legacy = User {
+            id: Uuid::new_v4(),
+            username: "bob@legacy".to_string(),
+            email: "bob@example.com".to_string(),
+            password_hash: state.password_hasher.hash(TEST_PASSWORD).unwrap(),
+            roles: vec![ROLE_USER.to_string()],
+        };
+        state
+            .auth_repository
+            .create(&UserModel::from(legacy))
+            .await
+            .unwrap();
 
-    Ok(Json(tokens))
+        for login_name in [
+            "Alice",
+            "ALICE@example.com",
+            "bob@legacy",
+            "bob@example.com",
+        ] {
+            let response = log_in(&state, login_name).await;
+            assert!(
+                matches!(response, Ok(LoginResponse::Tokens(_))),
+                "{}",
+                login_name
+            );
+        }
+        let (status, _) = log_in(&state, "nobody@example.com").await.err().unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
 }
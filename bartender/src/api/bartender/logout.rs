@@ -0,0 +1,240 @@
+use crate::api::cookies::{clear_session_cookies, refresh_token};
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::{internal_error, revoke_sessions, user_id, validate_payload};
+use crate::api::payload::LogoutPayload;
+use crate::app::AppState;
+use auth::claims::TokenUse;
+use auth::cookies::request_token;
+use auth::hashing::hash_token;
+use auth::AuthenticatedUser;
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::{Extension, Json};
+use axum_extra::extract::CookieJar;
+use std::sync::Arc;
+
+/// Revokes the access token the request was made with, if any. An invalid one has
+/// nothing left to revoke.
+async fn revoke_access_token(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let token = request_token(headers, &Method::POST).map_err(|err| {
+        (
+            err.status(),
+            Json(ErrorResponse {
+                message: err.message().to_string(),
+                details: None,
+            }),
+        )
+    })?;
+    let Some(token) = token else {
+        return Ok(());
+    };
+
+    match state.token_manager.validate_token(&token, TokenUse::Access) {
+        Ok(claims) => state
+            .token_manager
+            .revoke(&claims)
+            .await
+            .map_err(|_| internal_error()),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Ends the session of the refresh token: it, every token rotated from the same login and
+/// the access tokens of the session are revoked. The access token sent along, if any, is
+/// revoked too.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    request_body = LogoutPayload,
+    responses(
+        (status = 204, description = "Logged out, also clears session cookies when enabled"),
+        (status = 400, description = "No refresh token", body = ErrorResponse),
+        (status = 403, description = "CSRF token is invalid", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn logout(
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(payload): Json<LogoutPayload>,
+) -> Result<(CookieJar, StatusCode), (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let refresh_token = refresh_token(payload.refresh_token, &headers)?;
+
+    // Unknown tokens are not an error, like in `revoke`
+    let session_id = state
+        .refresh_token_repository
+        .revoke_family_of(&hash_token(&refresh_token))
+        .await
+        .map_err(|_| internal_error())?;
+    revoke_sessions(&state, session_id.as_slice()).await?;
+    revoke_access_token(&state, &headers).await?;
+
+    Ok((clear_session_cookies(&state, jar), StatusCode::NO_CONTENT))
+}
+
+/// Ends every session of the user by revoking all of its refresh and access tokens. Needs
+/// the access token of a login session: a personal access token can't end the sessions.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout-all",
+    responses(
+        (status = 204, description = "All sessions ended, also clears session cookies when enabled"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not authenticated with a login session", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn logout_all(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Result<(CookieJar, StatusCode), (StatusCode, Json<ErrorResponse>)> {
+    let user_id = user_id(&user)?;
+    if user.session_id.is_none() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                message: "Only a login session can end all sessions".to_string(),
+                details: None,
+            }),
+        ));
+    }
+
+    let session_ids = state
+        .refresh_token_repository
+        .revoke_all(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+    revoke_sessions(&state, &session_ids).await?;
+    revoke_access_token(&state, &headers).await?;
+
+    Ok((clear_session_cookies(&state, jar), StatusCode::NO_CONTENT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::bartender::refresh::refresh;
+    use crate::api::entities::AccessTokens;
+    use crate::api::helpers::start_session;
+    use crate::api::payload::RefreshPayload;
+    use crate::api::test_util::{create_test_user, test_client, test_state};
+    use auth::claims::Principal;
+    use axum::http::header::AUTHORIZATION;
+    use models::user::User;
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    fn bearer(tokens: &AccessTokens) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", tokens.access_token).parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn login(state: &Arc<AppState>, user: &User) -> AccessTokens {
+        start_session(state, &test_client(), user, &state.scopes)
+            .await
+            .unwrap()
+    }
+
+    async fn can_refresh(state: &Arc<AppState>, tokens: &AccessTokens) -> bool {
+        refresh(
+            Extension(state.clone()),
+            test_client(),
+            HeaderMap::new(),
+            CookieJar::new(),
+            Json(RefreshPayload {
+                refresh_token: Some(tokens.refresh_token.clone()),
+                scope: None,
+            }),
+        )
+        .await
+        .is_ok()
+    }
+
+    async fn is_revoked(state: &Arc<AppState>, tokens: &AccessTokens) -> bool {
+        let claims = state
+            .token_manager
+            .validate_token(&tokens.access_token, TokenUse::Access)
+            .unwrap();
+        state.token_manager.is_revoked(&claims).await.unwrap()
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_logout_ends_the_session(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = login(&state, &user).await;
+        let other = login(&state, &user).await;
+
+        let (_, status) = logout(
+            Extension(state.clone()),
+            HeaderMap::new(),
+            CookieJar::new(),
+            Json(LogoutPayload {
+                refresh_token: Some(tokens.refresh_token.clone()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(!can_refresh(&state, &tokens).await);
+        assert!(is_revoked(&state, &tokens).await);
+        assert!(!is_revoked(&state, &other).await);
+        assert!(can_refresh(&state, &other).await);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_logout_all_ends_every_session(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = login(&state, &user).await;
+        let other = login(&state, &user).await;
+        let authenticated = state.token_manager.decode_jwt(&tokens.access_token).unwrap();
+
+        let (_, status) = logout_all(
+            Extension(state.clone()),
+            authenticated,
+            bearer(&tokens),
+            CookieJar::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        for tokens in [&tokens, &other] {
+            assert!(is_revoked(&state, tokens).await);
+            assert!(!can_refresh(&state, tokens).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logout_all_needs_a_session() {
+        let pool = PgPool::connect_lazy("postgres://localhost/bartender").unwrap();
+        let state = test_state(pool);
+        let user = AuthenticatedUser {
+            id: Uuid::new_v4().to_string(),
+            roles: vec![],
+            scopes: vec![],
+            principal: Principal::User,
+            session_id: None,
+        };
+
+        let (status, _) = logout_all(Extension(state), user, HeaderMap::new(), CookieJar::new())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,630 @@
+use crate::api::client::ClientInfo;
+use crate::api::cookies::set_session_cookies;
+use crate::api::entities::{
+    AccessTokens, ErrorResponse, MfaChallenge, RecoveryCodes, TotpEnrollment,
+};
+use crate::api::helpers::{
+    end_other_sessions, ensure_not_disabled, hash_password, internal_error, random_token,
+    start_session, user_id, validate_payload, verify_password,
+};
+use crate::api::payload::{ConfirmTotpPayload, DisableTotpPayload, VerifyMfaPayload};
+use crate::api::throttle::{clear_failed_logins, reserve_attempt, LoginAttempt};
+use crate::app::AppState;
+use crate::totp;
+use auth::hashing::hash_token;
+use auth::AuthenticatedUser;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use axum_extra::extract::CookieJar;
+use chrono::{Duration, Utc};
+use models::user::User;
+use rand::Rng;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Recovery codes issued when TOTP is confirmed.
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Without look-alike characters, codes are typed in from paper.
+const RECOVERY_CODE_CHARSET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+/// Wrong codes a challenge survives. The account is throttled on top of that.
+const MAX_CHALLENGE_FAILURES: i32 = 5;
+
+fn bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            message: message.to_string(),
+            details: None,
+        }),
+    )
+}
+
+fn unauthorized(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(ErrorResponse {
+            message: message.to_string(),
+            details: None,
+        }),
+    )
+}
+
+/// Codes formatted like `abcde-23456`.
+fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..10)
+                .map(|_| {
+                    RECOVERY_CODE_CHARSET[rng.gen_range(0..RECOVERY_CODE_CHARSET.len())] as char
+                })
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are hashed without the dash and whitespace, in lower case.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == 6 && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Challenge returned by login instead of the tokens. `scopes` are granted once it is passed.
+pub async fn create_mfa_challenge(
+    state: &Arc<AppState>,
+    user: &User,
+    scopes: &[String],
+) -> Result<MfaChallenge, (StatusCode, Json<ErrorResponse>)> {
+    let mfa_token = random_token();
+    let expires_at = Utc::now() + Duration::seconds(state.mfa.challenge_expiration as i64);
+    state
+        .mfa_challenge_repository
+        .create(user.id, &hash_token(&mfa_token), scopes, expires_at)
+        .await
+        .map_err(|_| internal_error())?;
+
+    Ok(MfaChallenge {
+        mfa_token,
+        expires_in: state.mfa.challenge_expiration,
+    })
+}
+
+/// Checks a TOTP code or spends a recovery code of the user. Each code is accepted once.
+async fn check_code(
+    state: &Arc<AppState>,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    let code = code.trim();
+    if !is_totp_code(code) {
+        return use_recovery_code(state, user_id, code).await;
+    }
+
+    let secret = match state.totp_repository.find(user_id).await {
+        Ok(Some(secret)) if secret.confirmed_at.is_some() => secret,
+        Ok(_) => return Ok(false),
+        Err(_) => return Err(internal_error()),
+    };
+    match totp::verify(&secret.secret, code, Utc::now().timestamp() as u64) {
+        Some(step) => state
+            .totp_repository
+            .use_step(user_id, step as i64)
+            .await
+            .map_err(|_| internal_error()),
+        None => Ok(false),
+    }
+}
+
+/// Spends the recovery code of the user that `code` matches. Codes are salted, so each
+/// unused one is verified in turn.
+async fn use_recovery_code(
+    state: &Arc<AppState>,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    let code = normalize_recovery_code(code);
+    let recovery_codes = state
+        .totp_repository
+        .unused_recovery_codes(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+    for recovery_code in recovery_codes {
+        if verify_password(state, &code, Some(&recovery_code.code_hash)).await {
+            return state
+                .totp_repository
+                .use_recovery_code(recovery_code.id)
+                .await
+                .map_err(|_| internal_error());
+        }
+    }
+    Ok(false)
+}
+
+/// Starts TOTP enrollment. The secret is only asked for on login once confirmed with
+/// `confirm_totp`. Enrolling again replaces a pending secret.
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/totp",
+    responses(
+        (status = 201, description = "Pending secret, add it to an authenticator app", body = TotpEnrollment),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication is already enabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn enroll_totp(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> Result<(StatusCode, Json<TotpEnrollment>), (StatusCode, Json<ErrorResponse>)> {
+    let user_id = user_id(&user)?;
+    let stored = match state.auth_repository.find_by_id(user_id).await {
+        Ok(model) => User::from(model),
+        Err(_) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    message: "User not found".to_string(),
+                    details: None,
+                }),
+            ));
+        }
+    };
+
+    let secret = totp::generate_secret();
+    let enrolled = state
+        .totp_repository
+        .enroll(user_id, &secret)
+        .await
+        .map_err(|_| internal_error())?;
+    if !enrolled {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                message: "Two-factor authentication is already enabled".to_string(),
+                details: None,
+            }),
+        ));
+    }
+
+    let otpauth_uri = totp::otpauth_uri(&secret, &state.mfa.issuer, &stored.email)
+        .map_err(|_| internal_error())?;
+    Ok((
+        StatusCode::CREATED,
+        Json(TotpEnrollment {
+            secret,
+            otpauth_uri,
+        }),
+    ))
+}
+
+/// Enables two-factor authentication once the authenticator app shows a valid code, and
+/// issues recovery codes. Recovery codes issued before stop working. Every other session is
+/// ended, the session of the token used here stays logged in.
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/totp/confirm",
+    request_body = ConfirmTotpPayload,
+    responses(
+        (status = 200, description = "Two-factor authentication enabled", body = RecoveryCodes),
+        (status = 400, description = "Validation failed, invalid code or no pending secret", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn confirm_totp(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(payload): Json<ConfirmTotpPayload>,
+) -> Result<Json<RecoveryCodes>, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let user_id = user_id(&user)?;
+
+    let secret = match state.totp_repository.find(user_id).await {
+        Ok(Some(secret)) if secret.confirmed_at.is_none() => secret,
+        Ok(_) => return Err(bad_request("No TOTP secret is pending confirmation")),
+        Err(_) => return Err(internal_error()),
+    };
+    let Some(step) = totp::verify(
+        &secret.secret,
+        payload.code.trim(),
+        Utc::now().timestamp() as u64,
+    ) else {
+        return Err(bad_request("Invalid code"));
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let mut code_hashes = Vec::with_capacity(recovery_codes.len());
+    for code in &recovery_codes {
+        let code_hash = hash_password(&state, &normalize_recovery_code(code))
+            .await
+            .map_err(|_| internal_error())?;
+        code_hashes.push(code_hash);
+    }
+    let confirmed = state
+        .totp_repository
+        .confirm(user_id, step as i64, &code_hashes)
+        .await
+        .map_err(|_| internal_error())?;
+    if !confirmed {
+        return Err(bad_request("No TOTP secret is pending confirmation"));
+    }
+    end_other_sessions(&state, &user, user_id).await?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Turns two-factor authentication off, deleting the secret and the recovery codes. Takes
+/// the password and a TOTP or recovery code, wrong ones count as failed logins.
+#[utoipa::path(
+    delete,
+    path = "/api/auth/mfa/totp",
+    request_body = DisableTotpPayload,
+    responses(
+        (status = 204, description = "Two-factor authentication disabled"),
+        (status = 400, description = "Validation failed, or password or code is incorrect", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients", body = ErrorResponse),
+        (status = 404, description = "User not found or two-factor authentication not enabled", body = ErrorResponse),
+        (status = 429, description = "Too many failed attempts from the client or for the account, `retry_after` seconds in details", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn disable_totp(
+    Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    user: AuthenticatedUser,
+    Json(payload): Json<DisableTotpPayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let user_id = user_id(&user)?;
+    let not_found = |message: &str| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                message: message.to_string(),
+                details: None,
+            }),
+        )
+    };
+
+    let stored = match state.auth_repository.find_by_id(user_id).await {
+        Ok(model) => User::from(model),
+        Err(_) => return Err(not_found("User not found")),
+    };
+    let enabled = state
+        .totp_repository
+        .is_enabled(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+    if !enabled {
+        return Err(not_found("Two-factor authentication is not enabled"));
+    }
+
+    let attempt = LoginAttempt::for_user(client.ip_address.as_deref(), user_id);
+    let attempt = reserve_attempt(&state, &attempt).await?;
+    if !verify_password(&state, &payload.password, Some(&stored.password_hash)).await
+        || !check_code(&state, user_id, &payload.code).await?
+    {
+        return Err(bad_request("Password or code is incorrect"));
+    }
+    clear_failed_logins(&state, attempt).await;
+
+    let deleted = state
+        .totp_repository
+        .delete(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+    if !deleted {
+        return Err(not_found("Two-factor authentication is not enabled"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Second step of a login with two-factor authentication: exchanges the challenge token of
+/// `login` and a TOTP or recovery code for the tokens. Wrong codes count as failed logins.
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/verify",
+    request_body = VerifyMfaPayload,
+    responses(
+        (status = 200, description = "Second factor verified, also sets session cookies when enabled", body = AccessTokens),
+        (status = 400, description = "Validation failed", body = ErrorResponse),
+        (status = 401, description = "Invalid or expired challenge token, or invalid code", body = ErrorResponse),
+        (status = 403, description = "User is disabled", body = ErrorResponse),
+        (status = 429, description = "Too many failed attempts from the client or for the account, `retry_after` seconds in details", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn verify_mfa(
+    Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Json(payload): Json<VerifyMfaPayload>,
+) -> Result<(CookieJar, Json<AccessTokens>), (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let token_hash = hash_token(&payload.mfa_token);
+
+    let challenge = match state
+        .mfa_challenge_repository
+        .find(&token_hash, MAX_CHALLENGE_FAILURES)
+        .await
+    {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => return Err(unauthorized("Invalid or expired MFA token")),
+        Err(_) => return Err(internal_error()),
+    };
+
+    let attempt = LoginAttempt::for_user(client.ip_address.as_deref(), challenge.user_id);
+    let attempt = reserve_attempt(&state, &attempt).await?;
+
+    if !check_code(&state, challenge.user_id, &payload.code).await? {
+        state
+            .mfa_challenge_repository
+            .record_failure(&token_hash)
+            .await
+            .map_err(|_| internal_error())?;
+        return Err(unauthorized("Invalid code"));
+    }
+    let consumed = state
+        .mfa_challenge_repository
+        .consume(&token_hash)
+        .await
+        .map_err(|_| internal_error())?;
+    if !consumed {
+        return Err(unauthorized("Invalid or expired MFA token"));
+    }
+    clear_failed_logins(&state, attempt).await;
+
+    let user = match state.auth_repository.find_by_id(challenge.user_id).await {
+        Ok(model) => User::from(model),
+        Err(_) => return Err(unauthorized("Invalid or expired MFA token")),
+    };
+    ensure_not_disabled(&state, &user).await?;
+
+    let tokens = start_session(&state, &client, &user, &challenge.scopes).await?;
+    let jar = set_session_cookies(&state, jar, &tokens);
+
+    Ok((jar, Json(tokens)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::bartender::login::login;
+    use crate::api::entities::LoginResponse;
+    use crate::api::payload::LoginPayload;
+    use crate::api::test_util::{create_test_user, test_client, test_state, TEST_PASSWORD};
+    use auth::claims::TokenUse;
+    use sqlx::PgPool;
+
+    fn now() -> u64 {
+        Utc::now().timestamp() as u64
+    }
+
+    async fn session(state: &Arc<AppState>, user: &User) -> AccessTokens {
+        start_session(state, &test_client(), user, &state.scopes)
+            .await
+            .unwrap()
+    }
+
+    fn authenticated(state: &Arc<AppState>, tokens: &AccessTokens) -> AuthenticatedUser {
+        state
+            .token_manager
+            .decode_jwt(&tokens.access_token)
+            .unwrap()
+    }
+
+    /// Enables TOTP from `tokens`' session, with the code of the current time step. Returns
+    /// the secret and the recovery codes.
+    async fn enable_totp(state: &Arc<AppState>, tokens: &AccessTokens) -> (String, Vec<String>) {
+        let (_, Json(enrollment)) =
+            enroll_totp(Extension(state.clone()), authenticated(state, tokens))
+                .await
+                .unwrap();
+        let Json(recovery_codes) = confirm_totp(
+            Extension(state.clone()),
+            authenticated(state, tokens),
+            Json(ConfirmTotpPayload {
+                code: totp::generate(&enrollment.secret, now()),
+            }),
+        )
+        .await
+        .unwrap();
+        (enrollment.secret, recovery_codes.recovery_codes)
+    }
+
+    async fn log_in(state: &Arc<AppState>) -> MfaChallenge {
+        let (_, Json(response)) = login(
+            Extension(state.clone()),
+            test_client(),
+            CookieJar::new(),
+            Json(LoginPayload {
+                login: "alice".to_string(),
+                password: TEST_PASSWORD.to_string(),
+                scope: None,
+            }),
+        )
+        .await
+        .unwrap();
+        match response {
+            LoginResponse::MfaRequired(challenge) => challenge,
+            LoginResponse::Tokens(_) => panic!("Login didn't ask for a code"),
+        }
+    }
+
+    async fn verify(
+        state: &Arc<AppState>,
+        challenge: &MfaChallenge,
+        code: &str,
+    ) -> Result<AccessTokens, StatusCode> {
+        verify_mfa(
+            Extension(state.clone()),
+            test_client(),
+            CookieJar::new(),
+            Json(VerifyMfaPayload {
+                mfa_token: challenge.mfa_token.clone(),
+                code: code.to_string(),
+            }),
+        )
+        .await
+        .map(|(_, Json(tokens))| tokens)
+        .map_err(|(status, _)| status)
+    }
+
+    async fn is_revoked(state: &Arc<AppState>, tokens: &AccessTokens) -> bool {
+        let claims = state
+            .token_manager
+            .validate_token(&tokens.access_token, TokenUse::Access)
+            .unwrap();
+        state.token_manager.is_revoked(&claims).await.unwrap()
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_login_with_totp(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = session(&state, &user).await;
+        let other = session(&state, &user).await;
+
+        let (secret, _) = enable_totp(&state, &tokens).await;
+        assert!(!is_revoked(&state, &tokens).await);
+        assert!(is_revoked(&state, &other).await);
+
+        // The code of the current step was spent on confirming, the next one is accepted too
+        let code = totp::generate(&secret, now() + 30);
+        let challenge = log_in(&state).await;
+        let tokens = verify(&state, &challenge, &code).await.unwrap();
+        assert!(!is_revoked(&state, &tokens).await);
+        assert_eq!(
+            verify(&state, &challenge, &code).await.err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+
+        let replayed = log_in(&state).await;
+        assert_eq!(
+            verify(&state, &replayed, &code).await.err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_recovery_codes_are_spent(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = session(&state, &user).await;
+        let (_, recovery_codes) = enable_totp(&state, &tokens).await;
+
+        let code = recovery_codes[3].to_uppercase();
+        let challenge = log_in(&state).await;
+        assert!(verify(&state, &challenge, &code).await.is_ok());
+
+        let challenge = log_in(&state).await;
+        assert_eq!(
+            verify(&state, &challenge, &code).await.err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert!(verify(&state, &challenge, &recovery_codes[4]).await.is_ok());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_challenge_is_exhausted(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = session(&state, &user).await;
+        let (secret, _) = enable_totp(&state, &tokens).await;
+
+        let challenge = log_in(&state).await;
+        for _ in 0..MAX_CHALLENGE_FAILURES {
+            assert_eq!(
+                verify(&state, &challenge, "000000").await.err(),
+                Some(StatusCode::UNAUTHORIZED)
+            );
+        }
+        let code = totp::generate(&secret, now() + 30);
+        assert_eq!(
+            verify(&state, &challenge, &code).await.err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_disable_totp_needs_a_code(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = session(&state, &user).await;
+        let (_, recovery_codes) = enable_totp(&state, &tokens).await;
+        let disable = |code: &str| {
+            disable_totp(
+                Extension(state.clone()),
+                test_client(),
+                authenticated(&state, &tokens),
+                Json(DisableTotpPayload {
+                    password: TEST_PASSWORD.to_string(),
+                    code: code.to_string(),
+                }),
+            )
+        };
+
+        let (status, _) = disable("000000").await.err().unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            disable(&recovery_codes[0]).await.unwrap(),
+            StatusCode::NO_CONTENT
+        );
+        assert!(!state.totp_repository.is_enabled(user.id).await.unwrap());
+    }
+
+    #[test]
+    fn test_login_response_is_tagged() {
+        let response = LoginResponse::MfaRequired(MfaChallenge {
+            mfa_token: "token".to_string(),
+            expires_in: 300,
+        });
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["status"], "mfa_required");
+        assert_eq!(json["mfa_token"], "token");
+    }
+
+    #[test]
+    fn test_recovery_codes_are_unique_and_formatted() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            assert_eq!(&code[5..6], "-");
+            assert!(!is_totp_code(code));
+        }
+
+        let mut unique = codes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), codes.len());
+    }
+
+    #[test]
+    fn test_normalize_recovery_code() {
+        assert_eq!(normalize_recovery_code("abcde-23456"), "abcde23456");
+        assert_eq!(normalize_recovery_code(" ABCDE 23456 "), "abcde23456");
+    }
+
+    #[test]
+    fn test_is_totp_code() {
+        assert!(is_totp_code("012345"));
+        assert!(!is_totp_code("12345"));
+        assert!(!is_totp_code("12345a"));
+    }
+}
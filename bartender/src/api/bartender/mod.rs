@@ -1,20 +1,55 @@
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post};
 use axum::Router;
 
+pub mod email_verification;
+pub mod introspect;
+pub mod jwks;
 pub mod login;
+pub mod logout;
+pub mod mfa;
+pub mod password;
+pub mod password_reset;
+pub mod personal_tokens;
 pub mod refresh;
 pub mod register;
+pub mod revoke;
+pub mod sessions;
+pub mod token;
 pub mod validate;
 
+pub use email_verification::{resend_verification_email, verify_email};
+pub use introspect::introspect;
+pub use jwks::jwks;
 pub use register::register;
 pub use login::login;
+pub use logout::{logout, logout_all};
+pub use mfa::{confirm_totp, disable_totp, enroll_totp, verify_mfa};
+pub use password::change_password;
+pub use password_reset::{confirm_password_reset, request_password_reset};
+pub use personal_tokens::{create_personal_token, delete_personal_token, list_personal_tokens};
 pub use refresh::refresh;
+pub use revoke::revoke;
+pub use sessions::{delete_session, list_sessions};
+pub use token::token;
 pub use validate::validate;
 
 // Export paths generated by utoipa
+pub use email_verification::{__path_resend_verification_email, __path_verify_email};
+pub use introspect::__path_introspect;
+pub use jwks::__path_jwks;
 pub use register::__path_register;
 pub use login::__path_login;
+pub use logout::{__path_logout, __path_logout_all};
+pub use mfa::{__path_confirm_totp, __path_disable_totp, __path_enroll_totp, __path_verify_mfa};
+pub use password::__path_change_password;
+pub use password_reset::{__path_confirm_password_reset, __path_request_password_reset};
+pub use personal_tokens::{
+    __path_create_personal_token, __path_delete_personal_token, __path_list_personal_tokens,
+};
 pub use refresh::__path_refresh;
+pub use revoke::__path_revoke;
+pub use sessions::{__path_delete_session, __path_list_sessions};
+pub use token::__path_token;
 pub use validate::__path_validate;
 
 pub fn router() -> Router {
@@ -22,5 +57,22 @@ pub fn router() -> Router {
         .route("/register", post(register))
         .route("/login", post(login))
         .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/logout-all", post(logout_all))
+        .route("/mfa/totp", post(enroll_totp).delete(disable_totp))
+        .route("/mfa/totp/confirm", post(confirm_totp))
+        .route("/mfa/verify", post(verify_mfa))
+        .route("/password", post(change_password))
+        .route("/password-reset", post(request_password_reset))
+        .route("/password-reset/confirm", post(confirm_password_reset))
+        .route("/verify-email", post(verify_email))
+        .route("/verify-email/resend", post(resend_verification_email))
+        .route("/revoke", post(revoke))
+        .route("/token", post(token))
         .route("/validate", get(validate))
+        .route("/introspect", post(introspect))
+        .route("/tokens", get(list_personal_tokens).post(create_personal_token))
+        .route("/tokens/{id}", delete(delete_personal_token))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/{id}", delete(delete_session))
 }
@@ -0,0 +1,179 @@
+use crate::api::client::ClientInfo;
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::{
+    end_other_sessions, hash_password, internal_error, user_id, validate_payload, verify_password,
+};
+use crate::api::payload::ChangePasswordPayload;
+use crate::api::throttle::{clear_failed_logins, reserve_attempt, LoginAttempt};
+use crate::app::AppState;
+use auth::AuthenticatedUser;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use models::user::User;
+use std::sync::Arc;
+
+/// Changes the password of the user. Every other session is ended, the session of the
+/// token used here stays logged in. Wrong current passwords count as failed logins of the
+/// account.
+#[utoipa::path(
+    post,
+    path = "/api/auth/password",
+    request_body = ChangePasswordPayload,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Validation failed or current password is wrong", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 429, description = "Too many failed attempts from the client or for the account, `retry_after` seconds in details", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn change_password(
+    Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    user: AuthenticatedUser,
+    Json(payload): Json<ChangePasswordPayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let user_id = user_id(&user)?;
+
+    let stored = match state.auth_repository.find_by_id(user_id).await {
+        Ok(model) => User::from(model),
+        Err(_) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    message: "User not found".to_string(),
+                    details: None,
+                }),
+            ));
+        }
+    };
+    let attempt = LoginAttempt::for_user(client.ip_address.as_deref(), user_id);
+    let attempt = reserve_attempt(&state, &attempt).await?;
+    if !verify_password(
+        &state,
+        &payload.current_password,
+        Some(&stored.password_hash),
+    )
+    .await
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                message: "Current password is incorrect".to_string(),
+                details: None,
+            }),
+        ));
+    }
+    clear_failed_logins(&state, attempt).await;
+
+    let password_hash = hash_password(&state, &payload.new_password)
+        .await
+        .map_err(|_| internal_error())?;
+    state
+        .auth_repository
+        .set_password(user_id, &password_hash)
+        .await
+        .map_err(|_| internal_error())?;
+
+    end_other_sessions(&state, &user, user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::entities::AccessTokens;
+    use crate::api::helpers::start_session;
+    use crate::api::test_util::{create_test_user, test_client, test_state, TEST_PASSWORD};
+    use crate::config::{LoginThrottleConfig, ThrottlePolicy};
+    use auth::claims::TokenUse;
+    use models::user::User;
+    use sqlx::PgPool;
+
+    async fn login(state: &Arc<AppState>, user: &User) -> AccessTokens {
+        start_session(state, &test_client(), user, &state.scopes)
+            .await
+            .unwrap()
+    }
+
+    async fn is_revoked(state: &Arc<AppState>, tokens: &AccessTokens) -> bool {
+        let claims = state
+            .token_manager
+            .validate_token(&tokens.access_token, TokenUse::Access)
+            .unwrap();
+        state.token_manager.is_revoked(&claims).await.unwrap()
+    }
+
+    async fn change(
+        state: &Arc<AppState>,
+        tokens: &AccessTokens,
+        current_password: &str,
+    ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+        let authenticated = state
+            .token_manager
+            .decode_jwt(&tokens.access_token)
+            .unwrap();
+        change_password(
+            Extension(state.clone()),
+            test_client(),
+            authenticated,
+            Json(ChangePasswordPayload {
+                current_password: current_password.to_string(),
+                new_password: "NewPassword123!".to_string(),
+            }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_change_password_keeps_only_the_current_session(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = login(&state, &user).await;
+        let other = login(&state, &user).await;
+
+        let status = change(&state, &tokens, TEST_PASSWORD).await.unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(!is_revoked(&state, &tokens).await);
+        assert!(is_revoked(&state, &other).await);
+        let families = state
+            .refresh_token_repository
+            .revoke_all(user.id)
+            .await
+            .unwrap();
+        assert_eq!(families.len(), 1);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_wrong_current_passwords_are_throttled(pool: PgPool) {
+        let policy = ThrottlePolicy {
+            free_attempts: 1,
+            base_delay: 60,
+            max_delay: 60,
+            reset_after: 3600,
+        };
+        let state = Arc::into_inner(test_state(pool))
+            .unwrap()
+            .with_login_throttle(LoginThrottleConfig {
+                account: policy,
+                ip: policy,
+            });
+        let state = Arc::new(state);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = login(&state, &user).await;
+
+        for _ in 0..2 {
+            let (status, _) = change(&state, &tokens, "Wrong123!").await.err().unwrap();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        let (status, _) = change(&state, &tokens, TEST_PASSWORD).await.err().unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::api::client::ClientInfo;
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::{
+    hash_password, internal_error, random_token, revoke_sessions, validate_payload,
+};
+use crate::api::payload::{ConfirmPasswordResetPayload, PasswordResetPayload};
+use crate::api::throttle::{clear_failed_logins, release_attempt, reserve_attempt, LoginAttempt};
+use crate::app::AppState;
+use crate::mailer::Email;
+use anyhow::anyhow;
+use auth::hashing::hash_token;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::{Duration, Utc};
+use log::error;
+use models::user::User;
+use repository::auth::AuthRepositoryError;
+use std::sync::Arc;
+
+/// Emails a single-use password reset token. The user is looked up and the email sent after
+/// the response, which is the same, and as fast, whether the email belongs to a user or not.
+/// Requests are throttled per client IP and per email like logins, see `login_throttle`.
+#[utoipa::path(
+    post,
+    path = "/api/auth/password-reset",
+    request_body = PasswordResetPayload,
+    responses(
+        (status = 202, description = "Reset token emailed if a user has this email"),
+        (status = 400, description = "Validation failed", body = ErrorResponse),
+        (status = 429, description = "Too many requests from the client or for the email, `retry_after` seconds in details", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn request_password_reset(
+    Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    Json(payload): Json<PasswordResetPayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    // Every request counts, none is taken back
+    let attempt = LoginAttempt::password_reset(client.ip_address.as_deref(), &payload.email);
+    reserve_attempt(&state, &attempt).await?;
+
+    tokio::spawn(async move {
+        if let Err(err) = send_password_reset(&state, &payload.email).await {
+            error!("Failed to send password reset email: {}", err);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Issues a reset token for the user with `email` and mails it. Nothing is sent when no user
+/// has this email.
+async fn send_password_reset(state: &AppState, email: &str) -> anyhow::Result<()> {
+    let user = match state.auth_repository.find_by_email(email).await {
+        Ok(user) => User::from(user),
+        Err(AuthRepositoryError::UserNotFound) => return Ok(()),
+        Err(err) => return Err(anyhow!("Can't find the user: {:?}", err)),
+    };
+
+    let token = random_token();
+    let expires_at = Utc::now() + Duration::seconds(state.password_reset_expiration as i64);
+    state
+        .password_reset_repository
+        .create(user.id, &hash_token(&token), expires_at)
+        .await?;
+
+    let email = Email {
+        to: user.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Use this token to set a new password for {}:\r\n\r\n{}\r\n\r\n\
+             It expires in {} minutes. If you didn't ask for a reset, ignore this email.",
+            user.username,
+            token,
+            state.password_reset_expiration / 60
+        ),
+    };
+    state.mailer.send(&email).await
+}
+
+/// Sets a new password with a token from `request_password_reset`. All sessions of the user
+/// are ended. Invalid tokens are throttled per client IP.
+#[utoipa::path(
+    post,
+    path = "/api/auth/password-reset/confirm",
+    request_body = ConfirmPasswordResetPayload,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Validation failed, or invalid or expired token", body = ErrorResponse),
+        (status = 429, description = "Too many invalid tokens from the client, `retry_after` seconds in details", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn confirm_password_reset(
+    Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    Json(payload): Json<ConfirmPasswordResetPayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let attempt = LoginAttempt::password_reset_confirmation(client.ip_address.as_deref());
+    let attempt = reserve_attempt(&state, &attempt).await?;
+
+    // The token is checked before the password is hashed, so that guessing tokens is cheap
+    // for the server too
+    let token_hash = hash_token(&payload.token);
+    match state
+        .password_reset_repository
+        .find_valid(&token_hash)
+        .await
+    {
+        Ok(Some(_)) => clear_failed_logins(&state, attempt).await,
+        Ok(None) => return Err(invalid_reset_token()),
+        Err(_) => {
+            release_attempt(&state, attempt).await;
+            return Err(internal_error());
+        }
+    }
+
+    let password_hash = hash_password(&state, &payload.password)
+        .await
+        .map_err(|_| internal_error())?;
+    // `None` when a concurrent confirmation used the token first
+    let user_id = state
+        .password_reset_repository
+        .reset_password(&token_hash, &password_hash)
+        .await
+        .map_err(|_| internal_error())?
+        .ok_or_else(invalid_reset_token)?;
+
+    let families = state
+        .refresh_token_repository
+        .revoke_all(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+    revoke_sessions(&state, &families).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn invalid_reset_token() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            message: "Invalid or expired reset token".to_string(),
+            details: None,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::helpers::start_session;
+    use crate::api::test_util::{create_test_user, test_client, test_state, TEST_PASSWORD};
+    use crate::config::{LoginThrottleConfig, ThrottlePolicy};
+    use auth::claims::TokenUse;
+    use sqlx::PgPool;
+
+    /// Locks out for a minute after two failures
+    fn throttled_state(pool: PgPool) -> Arc<AppState> {
+        let policy = ThrottlePolicy {
+            free_attempts: 2,
+            base_delay: 60,
+            max_delay: 60,
+            reset_after: 3600,
+        };
+        let state = Arc::into_inner(test_state(pool))
+            .unwrap()
+            .with_login_throttle(LoginThrottleConfig {
+                account: policy,
+                ip: policy,
+            });
+        Arc::new(state)
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_reset_sets_the_password_once(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let tokens = start_session(&state, &test_client(), &user, &state.scopes)
+            .await
+            .unwrap();
+        let token = random_token();
+        state
+            .password_reset_repository
+            .create(
+                user.id,
+                &hash_token(&token),
+                Utc::now() + Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        let payload = || ConfirmPasswordResetPayload {
+            token: token.clone(),
+            password: "NewPassword123!".to_string(),
+        };
+
+        let status =
+            confirm_password_reset(Extension(state.clone()), test_client(), Json(payload()))
+                .await
+                .unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let stored = User::from(state.auth_repository.find_by_id(user.id).await.unwrap());
+        assert!(state
+            .password_hasher
+            .verify("NewPassword123!", &stored.password_hash));
+        assert!(!state
+            .password_hasher
+            .verify(TEST_PASSWORD, &stored.password_hash));
+        let claims = state
+            .token_manager
+            .validate_token(&tokens.access_token, TokenUse::Access)
+            .unwrap();
+        assert!(state.token_manager.is_revoked(&claims).await.unwrap());
+
+        let (status, _) = confirm_password_reset(Extension(state), test_client(), Json(payload()))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_invalid_tokens_are_throttled(pool: PgPool) {
+        let state = throttled_state(pool);
+        let confirm = || {
+            confirm_password_reset(
+                Extension(state.clone()),
+                test_client(),
+                Json(ConfirmPasswordResetPayload {
+                    token: random_token(),
+                    password: "NewPassword123!".to_string(),
+                }),
+            )
+        };
+
+        for _ in 0..=state.login_throttle.ip.free_attempts {
+            let (status, _) = confirm().await.err().unwrap();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        let (status, _) = confirm().await.err().unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_reset_requests_are_throttled(pool: PgPool) {
+        let state = throttled_state(pool);
+        let request = |email: &str| {
+            request_password_reset(
+                Extension(state.clone()),
+                ClientInfo {
+                    user_agent: None,
+                    ip_address: None,
+                },
+                Json(PasswordResetPayload {
+                    email: email.to_string(),
+                }),
+            )
+        };
+
+        for _ in 0..=state.login_throttle.account.free_attempts {
+            let status = request("alice@example.com").await.unwrap();
+            assert_eq!(status, StatusCode::ACCEPTED);
+        }
+        let (status, _) = request("Alice@Example.com").await.err().unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let status = request("bob@example.com").await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::api::entities::{CreatedPersonalToken, ErrorResponse, PersonalTokenResponse};
+use crate::api::helpers::{internal_error, narrow_scopes, user_id, validate_payload};
+use crate::api::payload::CreatePersonalTokenPayload;
+use crate::app::AppState;
+use auth::personal_tokens::generate_personal_token;
+use auth::AuthenticatedUser;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Issues a long-lived token for scripts and CI. Its scopes can't exceed those of the token
+/// used to create it, which must belong to a login session rather than be a personal access
+/// token itself.
+#[utoipa::path(
+    post,
+    path = "/api/auth/tokens",
+    request_body = CreatePersonalTokenPayload,
+    responses(
+        (status = 201, description = "Token created, its value is only shown now", body = CreatedPersonalToken),
+        (status = 400, description = "Validation failed or scope exceeds the current token", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not authenticated with a login session, or a service client", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn create_personal_token(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(payload): Json<CreatePersonalTokenPayload>,
+) -> Result<(StatusCode, Json<CreatedPersonalToken>), (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    let user_id = user_id(&user)?;
+    // Otherwise a leaked expiring token could be traded for one that never expires
+    if user.session_id.is_none() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                message: "Personal access tokens can only be created from a login session"
+                    .to_string(),
+                details: None,
+            }),
+        ));
+    }
+    let scopes = narrow_scopes(&user.scopes, payload.scope.as_deref())?;
+    let expires_at = match payload.expires_in {
+        Some(expires_in) => Some(
+            Duration::try_seconds(expires_in as i64)
+                .and_then(|expires_in| Utc::now().checked_add_signed(expires_in))
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(ErrorResponse {
+                            message: "Expiration is too far in the future".to_string(),
+                            details: None,
+                        }),
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    let (token, token_hash) = generate_personal_token();
+    let model = state
+        .personal_token_repository
+        .create(user_id, &payload.name, &token_hash, &scopes, expires_at)
+        .await
+        .map_err(|_| internal_error())?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedPersonalToken {
+            token,
+            personal_token: PersonalTokenResponse::from(model),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/tokens",
+    responses(
+        (status = 200, description = "Personal access tokens of the user", body = [PersonalTokenResponse]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn list_personal_tokens(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<PersonalTokenResponse>>, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = user_id(&user)?;
+
+    let tokens = state
+        .personal_token_repository
+        .list(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+
+    Ok(Json(
+        tokens.into_iter().map(PersonalTokenResponse::from).collect(),
+    ))
+}
+
+/// Revokes a personal access token. It is rejected from the next request on.
+#[utoipa::path(
+    delete,
+    path = "/api/auth/tokens/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Personal access token id")
+    ),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients", body = ErrorResponse),
+        (status = 404, description = "Token not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn delete_personal_token(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = user_id(&user)?;
+
+    let deleted = state
+        .personal_token_repository
+        .delete(id, user_id)
+        .await
+        .map_err(|_| internal_error())?;
+
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                message: "Token not found".to_string(),
+                details: None,
+            }),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::claims::Principal;
+    use auth::test_util::test_token_manager;
+    use sqlx::PgPool;
+
+    async fn create(
+        session_id: Option<&str>,
+        expires_in: Option<u64>,
+    ) -> Result<(StatusCode, Json<CreatedPersonalToken>), (StatusCode, Json<ErrorResponse>)> {
+        let pool = PgPool::connect_lazy("postgres://localhost/bartender").unwrap();
+        let state = Arc::new(AppState::new(pool, test_token_manager(), vec![]));
+        let user = AuthenticatedUser {
+            id: Uuid::new_v4().to_string(),
+            roles: vec![],
+            scopes: vec![],
+            principal: Principal::User,
+            session_id: session_id.map(String::from),
+        };
+
+        create_personal_token(
+            Extension(state),
+            user,
+            Json(CreatePersonalTokenPayload {
+                name: "ci".to_string(),
+                scope: None,
+                expires_in,
+            }),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_personal_token_cannot_create_another() {
+        let (status, _) = create(None, Some(60)).await.err().unwrap();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_expiration_is_bounded() {
+        let (status, _) = create(Some("session"), Some(u64::MAX)).await.err().unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
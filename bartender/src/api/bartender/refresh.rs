@@ -1,38 +1,52 @@
+use crate::api::client::ClientInfo;
+use crate::api::cookies::{refresh_token, set_session_cookies};
 use crate::api::entities::{AccessTokens, ErrorResponse};
-use crate::api::helpers::{generate_tokens, validate_payload};
+use crate::api::helpers::{
+    ensure_not_disabled, ensure_not_revoked, generate_tokens, internal_error, narrow_scopes,
+    validate_payload,
+};
 use crate::api::payload::RefreshPayload;
 use crate::app::AppState;
-use auth::claims::Claims;
-use axum::http::StatusCode;
+use auth::claims::TokenUse;
+use auth::hashing::hash_token;
+use axum::http::{HeaderMap, StatusCode};
 use axum::{Extension, Json};
-use jsonwebtoken::{decode, Algorithm, Validation};
+use axum_extra::extract::CookieJar;
+use log::warn;
 use models::user::User;
+use repository::refresh_tokens::RefreshTokenUse;
 use std::sync::Arc;
-use uuid::Uuid;
 
+/// Exchanges a refresh token for new tokens. Each refresh token is single-use: presenting
+/// a used one again revokes every token issued since the login.
 #[utoipa::path(
     post,
     path = "/api/auth/refresh",
     request_body = RefreshPayload,
     responses(
-        (status = 200, description = "Tokens refreshed successfully", body = AccessTokens),
-        (status = 401, description = "Invalid or expired refresh token", body = ErrorResponse),
+        (status = 200, description = "Tokens refreshed successfully, also sets session cookies when enabled", body = AccessTokens),
+        (status = 400, description = "No refresh token or scope exceeds the original grant", body = ErrorResponse),
+        (status = 401, description = "Invalid, expired, revoked or already used refresh token", body = ErrorResponse),
+        (status = 403, description = "User is disabled or CSRF token is invalid", body = ErrorResponse),
         (status = 404, description = "User not found", body = ErrorResponse),
-        (status = 500, description = "Failed to generate access/refresh token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
     )
 )]
 pub async fn refresh(
     Extension(state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    jar: CookieJar,
     Json(payload): Json<RefreshPayload>,
-) -> Result<Json<AccessTokens>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(CookieJar, Json<AccessTokens>), (StatusCode, Json<ErrorResponse>)> {
     validate_payload(&payload)?;
+    let refresh_token = refresh_token(payload.refresh_token, &headers)?;
 
-    let token_data = match decode::<Claims>(
-        &payload.refresh_token,
-        state.token_manager.get_decoding_key(),
-        &Validation::new(Algorithm::HS256),
-    ) {
-        Ok(data) => data,
+    let claims = match state
+        .token_manager
+        .validate_token(&refresh_token, TokenUse::Refresh)
+    {
+        Ok(claims) => claims,
         Err(_) => {
             return Err((
                 StatusCode::UNAUTHORIZED,
@@ -44,20 +58,52 @@ pub async fn refresh(
         }
     };
 
-    let user_id = match Uuid::parse_str(&token_data.claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
+    ensure_not_revoked(&state, &claims).await?;
+
+    let scopes = narrow_scopes(&claims.scopes(), payload.scope.as_deref())?;
+
+    let token = match state
+        .refresh_token_repository
+        .consume(&hash_token(&refresh_token))
+        .await
+    {
+        Ok(RefreshTokenUse::Rotated(token)) => token,
+        Ok(RefreshTokenUse::Reused(token)) => {
+            warn!(
+                "Refresh token reuse detected for user {}, revoking its session",
+                token.user_id
+            );
+            state
+                .refresh_token_repository
+                .revoke_family(token.family_id)
+                .await
+                .map_err(|_| internal_error())?;
+            state
+                .token_manager
+                .revoke_session(&token.family_id.to_string())
+                .await
+                .map_err(|_| internal_error())?;
             return Err((
                 StatusCode::UNAUTHORIZED,
                 Json(ErrorResponse {
-                    message: "Invalid refresh token".to_string(),
+                    message: "Refresh token has already been used".to_string(),
                     details: None,
                 }),
             ));
         }
+        Ok(RefreshTokenUse::Invalid) => {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(ErrorResponse {
+                    message: "Refresh token has been revoked".to_string(),
+                    details: None,
+                }),
+            ));
+        }
+        Err(_) => return Err(internal_error()),
     };
 
-    let user = match state.auth_repository.find_by_id(user_id).await {
+    let user = match state.auth_repository.find_by_id(token.user_id).await {
         Ok(user_model) => User::from(user_model),
         Err(_) => {
             return Err((
@@ -70,7 +116,123 @@ pub async fn refresh(
         }
     };
 
-    let tokens = generate_tokens(&state, &user)?;
+    ensure_not_disabled(&state, &user).await?;
+
+    state
+        .session_repository
+        .touch(
+            token.family_id,
+            client.user_agent.as_deref(),
+            client.ip_address.as_deref(),
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    let tokens = generate_tokens(&state, &user, &scopes, token.family_id).await?;
+
+    let jar = set_session_cookies(&state, jar, &tokens);
+
+    Ok((jar, Json(tokens)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::helpers::start_session;
+    use crate::api::test_util::{create_test_user, test_client, test_state};
+    use auth::test_util::{test_token_manager, test_user};
+    use chrono::Duration;
+    use sqlx::PgPool;
+
+    #[tokio::test]
+    async fn test_access_token_cannot_refresh() {
+        let token_manager = test_token_manager();
+        let user = test_user();
+        let access_token = token_manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+        let pool = PgPool::connect_lazy("postgres://localhost/bartender").unwrap();
+        let state = Arc::new(AppState::new(pool, token_manager, vec![]));
+
+        let result = refresh(
+            Extension(state),
+            ClientInfo {
+                user_agent: None,
+                ip_address: None,
+            },
+            HeaderMap::new(),
+            CookieJar::new(),
+            Json(RefreshPayload {
+                refresh_token: Some(access_token),
+                scope: None,
+            }),
+        )
+        .await;
+
+        match result {
+            Ok(_) => panic!("Expected access token to be rejected"),
+            Err((status, _)) => assert_eq!(status, StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    async fn call(
+        state: &Arc<AppState>,
+        refresh_token: &str,
+    ) -> Result<AccessTokens, (StatusCode, Json<ErrorResponse>)> {
+        refresh(
+            Extension(state.clone()),
+            test_client(),
+            HeaderMap::new(),
+            CookieJar::new(),
+            Json(RefreshPayload {
+                refresh_token: Some(refresh_token.to_string()),
+                scope: None,
+            }),
+        )
+        .await
+        .map(|(_, Json(tokens))| tokens)
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_refresh_token_is_rotated(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let first = start_session(&state, &test_client(), &user, &state.scopes)
+            .await
+            .unwrap();
+
+        let second = call(&state, &first.refresh_token).await.unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        let third = call(&state, &second.refresh_token).await.unwrap();
+        assert_ne!(third.refresh_token, second.refresh_token);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_reuse_revokes_the_session(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let first = start_session(&state, &test_client(), &user, &state.scopes)
+            .await
+            .unwrap();
+        let other = start_session(&state, &test_client(), &user, &state.scopes)
+            .await
+            .unwrap();
+        let second = call(&state, &first.refresh_token).await.unwrap();
+
+        let (status, _) = call(&state, &first.refresh_token).await.err().unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        // The token rotated from the replayed one is revoked along with the session
+        let (status, _) = call(&state, &second.refresh_token).await.err().unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let access = state
+            .token_manager
+            .validate_token(&second.access_token, TokenUse::Access)
+            .unwrap();
+        assert!(state.token_manager.is_revoked(&access).await.unwrap());
 
-    Ok(Json(tokens))
+        // Other sessions of the user are left alone
+        call(&state, &other.refresh_token).await.unwrap();
+    }
 }
@@ -1,12 +1,15 @@
+use crate::api::bartender::email_verification::send_verification_email;
 use crate::api::entities::{AccessTokens, ErrorResponse};
-use crate::api::helpers::validate_payload;
+use crate::api::helpers::{internal_error, validate_payload};
 use crate::api::payload::RegisterPayload;
 use crate::app::AppState;
 use axum::http::StatusCode;
 use axum::{debug_handler, Extension, Json};
+use log::error;
 use models::user::{User, UserModel};
 use std::sync::Arc;
 
+/// Creates a user and mails a token to verify its email with `verify_email`.
 #[utoipa::path(
     post,
     path = "/api/auth/register",
@@ -25,21 +28,23 @@ pub async fn register(
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
     validate_payload(&payload)?;
 
-    let user: User = match User::try_from(payload) {
-        Ok(user) => user,
-        Err(_) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    message: "Internal server error".to_string(),
-                    details: None,
-                }),
-            ));
-        }
-    };
+    // Hashing takes too long to run on the async runtime
+    let password_hasher = state.password_hasher.clone();
+    let user: User =
+        match tokio::task::spawn_blocking(move || payload.into_user(&*password_hasher)).await {
+            Ok(Ok(user)) => user,
+            _ => return Err(internal_error()),
+        };
 
-    match state.auth_repository.create(&UserModel::from(user)).await {
-        Ok(_) => Ok(StatusCode::CREATED),
+    let model = UserModel::from(user);
+    match state.auth_repository.create(&model).await {
+        Ok(_) => {
+            // The user can ask for another email, registration succeeded anyway
+            if let Err(e) = send_verification_email(&state, &User::from(model)).await {
+                error!("Failed to issue email verification token: {}", e);
+            }
+            Ok(StatusCode::CREATED)
+        }
         Err(e) => {
             if e.is_user_already_exists() {
                 Err((
@@ -50,13 +55,7 @@ pub async fn register(
                     }),
                 ))
             } else {
-                Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        message: "Internal server error".to_string(),
-                        details: None,
-                    }),
-                ))
+                Err(internal_error())
             }
         }
     }
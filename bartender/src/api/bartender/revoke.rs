@@ -0,0 +1,118 @@
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::validate_payload;
+use crate::api::payload::RevokePayload;
+use crate::app::AppState;
+use auth::claims::TokenUse;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use std::sync::Arc;
+
+/// Revokes an access or refresh token (RFC 7009). Holding the token is enough to revoke it.
+/// Invalid and expired tokens are not an error: there is nothing left to revoke.
+#[utoipa::path(
+    post,
+    path = "/api/auth/revoke",
+    request_body = RevokePayload,
+    responses(
+        (status = 200, description = "Token revoked or already invalid"),
+        (status = 400, description = "Validation failed", body = ErrorResponse),
+        (status = 500, description = "Failed to revoke token", body = ErrorResponse),
+    )
+)]
+pub async fn revoke(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<RevokePayload>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+
+    let claims = match state
+        .token_manager
+        .validate_token(&payload.token, TokenUse::Access)
+        .or_else(|_| {
+            state
+                .token_manager
+                .validate_token(&payload.token, TokenUse::Refresh)
+        }) {
+        Ok(claims) => claims,
+        Err(_) => return Ok(StatusCode::OK),
+    };
+
+    match state.token_manager.revoke(&claims).await {
+        Ok(_) => Ok(StatusCode::OK),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                message: "Failed to revoke token".to_string(),
+                details: None,
+            }),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::revocation::InMemoryRevocationStore;
+    use auth::test_util::{test_token_manager, test_user};
+    use chrono::Duration;
+    use sqlx::PgPool;
+
+    fn state() -> Arc<AppState> {
+        let token_manager = test_token_manager().with_revocation_store(
+            Arc::new(InMemoryRevocationStore::new()),
+            std::time::Duration::from_secs(60),
+        );
+        let pool = PgPool::connect_lazy("postgres://localhost/bartender").unwrap();
+        Arc::new(AppState::new(pool, token_manager, vec![]))
+    }
+
+    #[tokio::test]
+    async fn test_revoked_tokens_are_rejected() {
+        let state = state();
+        let user = test_user();
+        let access_token = state
+            .token_manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+        let refresh_token = state
+            .token_manager
+            .generate_refresh_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        for token in [&access_token, &refresh_token] {
+            let status = revoke(
+                Extension(state.clone()),
+                Json(RevokePayload {
+                    token: token.clone(),
+                }),
+            )
+            .await
+            .unwrap();
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let access = state
+            .token_manager
+            .validate_token(&access_token, TokenUse::Access)
+            .unwrap();
+        let refresh = state
+            .token_manager
+            .validate_token(&refresh_token, TokenUse::Refresh)
+            .unwrap();
+        assert!(state.token_manager.is_revoked(&access).await.unwrap());
+        assert!(state.token_manager.is_revoked(&refresh).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_ignored() {
+        let status = revoke(
+            Extension(state()),
+            Json(RevokePayload {
+                token: "not a token".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::OK);
+    }
+}
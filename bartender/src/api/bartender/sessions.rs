@@ -0,0 +1,156 @@
+use crate::api::entities::{ErrorResponse, SessionResponse};
+use crate::api::helpers::{internal_error, revoke_sessions, user_id};
+use crate::app::AppState;
+use auth::AuthenticatedUser;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Personal access tokens have no session, they can't see or end the sessions of the user.
+fn ensure_login_session(user: &AuthenticatedUser) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if user.session_id.is_none() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                message: "Only a login session can manage sessions".to_string(),
+                details: None,
+            }),
+        ));
+    }
+    Ok(())
+}
+
+/// Devices the user is logged in on: sessions with a refresh token that can still be used.
+#[utoipa::path(
+    get,
+    path = "/api/auth/sessions",
+    responses(
+        (status = 200, description = "Active sessions of the user, most recently used first", body = [SessionResponse]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients or personal access tokens", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn list_sessions(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<SessionResponse>>, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = user_id(&user)?;
+    ensure_login_session(&user)?;
+
+    let sessions = state
+        .session_repository
+        .list_active(user_id)
+        .await
+        .map_err(|_| internal_error())?;
+
+    Ok(Json(
+        sessions.into_iter().map(SessionResponse::from).collect(),
+    ))
+}
+
+/// Logs a device out: its refresh token and its access tokens stop working.
+#[utoipa::path(
+    delete,
+    path = "/api/auth/sessions/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Session id")
+    ),
+    responses(
+        (status = 204, description = "Session revoked"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not available to service clients or personal access tokens", body = ErrorResponse),
+        (status = 404, description = "Session not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn delete_session(
+    Extension(state): Extension<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = user_id(&user)?;
+    ensure_login_session(&user)?;
+
+    let revoked = state
+        .session_repository
+        .revoke(id, user_id)
+        .await
+        .map_err(|_| internal_error())?;
+
+    if !revoked {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                message: "Session not found".to_string(),
+                details: None,
+            }),
+        ));
+    }
+    revoke_sessions(&state, &[id]).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::helpers::start_session;
+    use crate::api::test_util::{create_test_user, test_client, test_state};
+    use auth::claims::{Principal, TokenUse};
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_deleted_session_rejects_its_access_token(pool: PgPool) {
+        let state = test_state(pool);
+        let user = create_test_user(&state, "alice").await;
+        let current = start_session(&state, &test_client(), &user, &state.scopes)
+            .await
+            .unwrap();
+        let stolen = start_session(&state, &test_client(), &user, &state.scopes)
+            .await
+            .unwrap();
+        let claims = state
+            .token_manager
+            .validate_token(&stolen.access_token, TokenUse::Access)
+            .unwrap();
+        let id = Uuid::parse_str(claims.sid.as_deref().unwrap()).unwrap();
+
+        let authenticated = state
+            .token_manager
+            .decode_jwt(&current.access_token)
+            .unwrap();
+        let status = delete_session(Extension(state.clone()), authenticated, Path(id))
+            .await
+            .unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(state.token_manager.is_revoked(&claims).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_personal_tokens_cant_manage_sessions() {
+        let pool = PgPool::connect_lazy("postgres://localhost/bartender").unwrap();
+        let state = test_state(pool);
+        let user = AuthenticatedUser {
+            id: Uuid::new_v4().to_string(),
+            roles: vec![],
+            scopes: vec![],
+            principal: Principal::User,
+            session_id: None,
+        };
+
+        let (status, _) = list_sessions(Extension(state.clone()), user.clone())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = delete_session(Extension(state), user, Path(Uuid::new_v4()))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::api::entities::{ErrorResponse, ServiceToken};
+use crate::api::helpers::{authenticate_client, invalid_client, narrow_scopes, validate_payload};
+use crate::api::payload::ClientCredentialsPayload;
+use crate::app::AppState;
+use axum::http::StatusCode;
+use axum::{Extension, Form, Json};
+use axum_extra::TypedHeader;
+use chrono::Duration;
+use headers::authorization::Basic;
+use headers::Authorization;
+use std::sync::Arc;
+
+/// Token endpoint of the client-credentials grant, for service-to-service calls.
+#[utoipa::path(
+    post,
+    path = "/api/auth/token",
+    request_body(content = ClientCredentialsPayload, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Access token of the service client", body = ServiceToken),
+        (status = 400, description = "Unsupported grant type or unknown scope requested", body = ErrorResponse),
+        (status = 401, description = "Invalid client credentials", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+pub async fn token(
+    Extension(state): Extension<Arc<AppState>>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(payload): Form<ClientCredentialsPayload>,
+) -> Result<Json<ServiceToken>, (StatusCode, Json<ErrorResponse>)> {
+    validate_payload(&payload)?;
+    if payload.grant_type != "client_credentials" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                message: "Unsupported grant type".to_string(),
+                details: None,
+            }),
+        ));
+    }
+
+    let (client_id, client_secret) = match basic {
+        Some(TypedHeader(Authorization(basic))) => {
+            (basic.username().to_string(), basic.password().to_string())
+        }
+        None => match (payload.client_id, payload.client_secret) {
+            (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+            _ => return Err(invalid_client()),
+        },
+    };
+
+    let client = authenticate_client(&state, &client_id, &client_secret).await?;
+    let scopes = narrow_scopes(&client.scopes, payload.scope.as_deref())?;
+    let access_token = state
+        .token_manager
+        .generate_service_token(
+            &client.client_id,
+            &scopes,
+            Duration::seconds(state.client_token_expiration as i64),
+        )
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    message: "Failed to generate access token".to_string(),
+                    details: None,
+                }),
+            )
+        })?;
+
+    Ok(Json(ServiceToken {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: state.client_token_expiration,
+        scope: scopes.join(" "),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::test_state;
+    use sqlx::PgPool;
+
+    async fn request(
+        state: &Arc<AppState>,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<Json<ServiceToken>, (StatusCode, Json<ErrorResponse>)> {
+        token(
+            Extension(state.clone()),
+            None,
+            Form(ClientCredentialsPayload {
+                grant_type: "client_credentials".to_string(),
+                client_id: Some(client_id.to_string()),
+                client_secret: Some(client_secret.to_string()),
+                scope: None,
+            }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_client_secrets_are_verified(pool: PgPool) {
+        let state = test_state(pool);
+        let scopes = vec!["tasks:read".to_string()];
+        let secret_hash = state.password_hasher.hash("secret").unwrap();
+        // Hashed by earlier versions
+        let legacy_hash = bcrypt::hash("legacy", 4).unwrap();
+        let repository = &state.service_client_repository;
+        repository
+            .create("current", "Current", &secret_hash, &scopes)
+            .await
+            .unwrap();
+        repository
+            .create("legacy", "Legacy", &legacy_hash, &scopes)
+            .await
+            .unwrap();
+
+        assert!(request(&state, "current", "secret").await.is_ok());
+        assert!(request(&state, "legacy", "legacy").await.is_ok());
+        for (client_id, client_secret) in [("current", "legacy"), ("unknown", "secret")] {
+            let (status, _) = request(&state, client_id, client_secret)
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+    }
+}
@@ -1,12 +1,12 @@
 use crate::api::entities::ErrorResponse;
+use crate::api::helpers::{ensure_not_revoked, ensure_user_active};
 use crate::app::AppState;
-use auth::claims::Claims;
+use auth::claims::{Principal, TokenUse};
 use axum::http::StatusCode;
 use axum::{Extension, Json};
 use axum_extra::TypedHeader;
 use headers::authorization::Bearer;
 use headers::Authorization;
-use jsonwebtoken::{decode, Algorithm, Validation};
 use serde::Serialize;
 use std::sync::Arc;
 use utoipa::ToSchema;
@@ -22,7 +22,8 @@ pub struct ValidateResponse {
     path = "/api/auth/validate",
     responses(
         (status = 200, description = "Token is valid", body = ValidateResponse),
-        (status = 401, description = "Invalid or expired token", body = ErrorResponse),
+        (status = 401, description = "Invalid, expired or revoked token, or disabled user", body = ErrorResponse),
+        (status = 500, description = "Failed to check token revocation or user", body = ErrorResponse),
     )
 )]
 pub async fn validate(
@@ -31,12 +32,8 @@ pub async fn validate(
 ) -> Result<Json<ValidateResponse>, (StatusCode, Json<ErrorResponse>)> {
     let token = bearer.token();
 
-    let token_data = match decode::<Claims>(
-        token,
-        state.token_manager.get_decoding_key(),
-        &Validation::new(Algorithm::HS256),
-    ) {
-        Ok(data) => data,
+    let claims = match state.token_manager.validate_token(token, TokenUse::Access) {
+        Ok(claims) => claims,
         Err(_) => {
             return Err((
                 StatusCode::UNAUTHORIZED,
@@ -48,8 +45,13 @@ pub async fn validate(
         }
     };
 
+    ensure_not_revoked(&state, &claims).await?;
+    if claims.principal == Principal::User {
+        ensure_user_active(&state, &claims.sub).await?;
+    }
+
     Ok(Json(ValidateResponse {
-        user_id: token_data.claims.sub,
+        user_id: claims.sub,
         message: "Token is valid".to_string(),
     }))
 }
@@ -0,0 +1,126 @@
+use crate::app::AppState;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::header::USER_AGENT;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Longest user agent kept, the header is sent by the client and can be anything.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Where a request comes from, recorded with sessions.
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    /// Peer address of the connection or, when the peer is one of the `trusted_proxies`, the
+    /// address it forwarded in `X-Forwarded-For`. `None` when unknown, which turns off the
+    /// login throttling per IP.
+    pub ip_address: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        let trusted_proxies = parts
+            .extensions
+            .get::<Arc<AppState>>()
+            .map(|state| state.trusted_proxies.as_slice())
+            .unwrap_or_default();
+        let ip_address = client_ip(peer, &parts.headers, trusted_proxies).map(|ip| ip.to_string());
+
+        Ok(ClientInfo {
+            user_agent,
+            ip_address,
+        })
+    }
+}
+
+/// Address of the client: the peer, unless it is a trusted proxy. Then the last address in
+/// `X-Forwarded-For` that isn't one of the proxies, as the ones before it are set by the
+/// client and can be anything.
+fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let forwarded: Vec<_> = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    forwarded
+        .into_iter()
+        .rev()
+        .map(|address| address.trim().parse::<IpAddr>().ok())
+        .find(|address| !matches!(address, Some(address) if trusted_proxies.contains(address)))
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    #[tokio::test]
+    async fn test_client_info() {
+        let address: SocketAddr = "192.0.2.1:50000".parse().unwrap();
+        let (mut parts, _) = Request::builder()
+            .header("User-Agent", "curl/8.5.0")
+            .extension(ConnectInfo(address))
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let client = ClientInfo::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(client.user_agent.as_deref(), Some("curl/8.5.0"));
+        assert_eq!(client.ip_address.as_deref(), Some("192.0.2.1"));
+
+        let (mut parts, _) = Request::builder().body(()).unwrap().into_parts();
+        let client = ClientInfo::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert!(client.user_agent.is_none());
+        assert!(client.ip_address.is_none());
+    }
+
+    #[test]
+    fn test_client_ip_behind_proxy() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Forwarded-For",
+            "203.0.113.7, 192.0.2.1, 10.0.0.1".parse().unwrap(),
+        );
+
+        assert_eq!(client_ip(Some(proxy), &headers, &[]), Some(proxy));
+        assert_eq!(client_ip(Some(proxy), &headers, &[proxy]), Some(client));
+        assert_eq!(client_ip(Some(client), &headers, &[proxy]), Some(client));
+        assert_eq!(client_ip(Some(proxy), &HeaderMap::new(), &[proxy]), None);
+
+        headers.insert("X-Forwarded-For", "192.0.2.1, garbage".parse().unwrap());
+        assert_eq!(client_ip(Some(proxy), &headers, &[proxy]), None);
+        assert_eq!(client_ip(None, &headers, &[proxy]), None);
+    }
+}
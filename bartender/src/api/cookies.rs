@@ -0,0 +1,221 @@
+use crate::api::entities::{AccessTokens, ErrorResponse};
+use crate::app::AppState;
+use crate::config::{CookieSameSite, SessionCookieConfig};
+use auth::cookies::{cookie, verify_csrf, ACCESS_TOKEN_COOKIE, CSRF_COOKIE, REFRESH_TOKEN_COOKIE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use time::Duration;
+use uuid::Uuid;
+
+/// The refresh token is only needed by the auth endpoints.
+const REFRESH_TOKEN_PATH: &str = "/api/auth";
+
+/// Adds the access and refresh tokens as `HttpOnly` cookies when `session_cookies` are
+/// enabled, plus a fresh CSRF token that the frontend echoes in the `X-CSRF-Token` header.
+pub fn set_session_cookies(state: &AppState, jar: CookieJar, tokens: &AccessTokens) -> CookieJar {
+    match &state.session_cookies {
+        Some(config) => add_session_cookies(
+            jar,
+            config,
+            tokens,
+            state.token_manager.refresh_token_expiration,
+        ),
+        None => jar,
+    }
+}
+
+/// Removes the session cookies on logout, when `session_cookies` are enabled.
+pub fn clear_session_cookies(state: &AppState, jar: CookieJar) -> CookieJar {
+    match &state.session_cookies {
+        Some(config) => remove_session_cookies(jar, config),
+        None => jar,
+    }
+}
+
+fn remove_session_cookies(jar: CookieJar, config: &SessionCookieConfig) -> CookieJar {
+    [
+        (ACCESS_TOKEN_COOKIE, "/"),
+        (REFRESH_TOKEN_COOKIE, REFRESH_TOKEN_PATH),
+        (CSRF_COOKIE, "/"),
+    ]
+    .into_iter()
+    .fold(jar, |jar, (name, path)| {
+        // The browser only drops a cookie with the same path and domain
+        let mut cookie = Cookie::build(name).path(path);
+        if let Some(domain) = &config.domain {
+            cookie = cookie.domain(domain.clone());
+        }
+        let mut cookie = cookie.build();
+        cookie.make_removal();
+        jar.add(cookie)
+    })
+}
+
+fn add_session_cookies(
+    jar: CookieJar,
+    config: &SessionCookieConfig,
+    tokens: &AccessTokens,
+    refresh_token_expiration: u64,
+) -> CookieJar {
+    let csrf_token = Uuid::new_v4().simple().to_string();
+
+    jar.add(session_cookie(
+        config,
+        ACCESS_TOKEN_COOKIE,
+        tokens.access_token.clone(),
+        "/",
+        tokens.expires_in,
+        true,
+    ))
+    .add(session_cookie(
+        config,
+        REFRESH_TOKEN_COOKIE,
+        tokens.refresh_token.clone(),
+        REFRESH_TOKEN_PATH,
+        refresh_token_expiration,
+        true,
+    ))
+    .add(session_cookie(
+        config,
+        CSRF_COOKIE,
+        csrf_token,
+        "/",
+        refresh_token_expiration,
+        false,
+    ))
+}
+
+/// Refresh token from the request body, otherwise from the refresh token cookie. The cookie
+/// is sent by the browser automatically, so it needs the CSRF header.
+pub fn refresh_token(
+    payload_token: Option<String>,
+    headers: &HeaderMap,
+) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(token) = payload_token {
+        return Ok(token);
+    }
+
+    let Some(token) = cookie(headers, REFRESH_TOKEN_COOKIE) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                message: "Refresh token must be provided".to_string(),
+                details: None,
+            }),
+        ));
+    };
+    verify_csrf(headers).map_err(|err| {
+        (
+            err.status(),
+            Json(ErrorResponse {
+                message: err.message().to_string(),
+                details: None,
+            }),
+        )
+    })?;
+    Ok(token)
+}
+
+fn session_cookie(
+    config: &SessionCookieConfig,
+    name: &'static str,
+    value: String,
+    path: &'static str,
+    max_age: u64,
+    http_only: bool,
+) -> Cookie<'static> {
+    let same_site = match config.same_site {
+        CookieSameSite::Strict => SameSite::Strict,
+        CookieSameSite::Lax => SameSite::Lax,
+    };
+    let mut cookie = Cookie::build((name, value))
+        .path(path)
+        .http_only(http_only)
+        .secure(true)
+        .same_site(same_site)
+        .max_age(Duration::seconds(max_age as i64));
+    if let Some(domain) = &config.domain {
+        cookie = cookie.domain(domain.clone());
+    }
+    cookie.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    #[test]
+    fn test_session_cookies() {
+        let config = SessionCookieConfig {
+            same_site: CookieSameSite::Strict,
+            domain: None,
+        };
+        let tokens = AccessTokens {
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            token_type: "Bearer".to_string(),
+            expires_in: 3600,
+            scope: String::new(),
+        };
+
+        let jar = add_session_cookies(CookieJar::new(), &config, &tokens, 604800);
+
+        let access = jar.get(ACCESS_TOKEN_COOKIE).unwrap();
+        assert_eq!(access.value(), "access");
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(true));
+        assert_eq!(access.same_site(), Some(SameSite::Strict));
+        assert_eq!(access.max_age(), Some(Duration::seconds(3600)));
+
+        let refresh = jar.get(REFRESH_TOKEN_COOKIE).unwrap();
+        assert_eq!(refresh.path(), Some(REFRESH_TOKEN_PATH));
+
+        // JavaScript has to read the CSRF token to send it back
+        let csrf = jar.get(CSRF_COOKIE).unwrap();
+        assert_eq!(csrf.http_only(), Some(false));
+        assert!(!csrf.value().is_empty());
+    }
+
+    #[test]
+    fn test_remove_session_cookies() {
+        let config = SessionCookieConfig {
+            same_site: CookieSameSite::Lax,
+            domain: Some("example.com".to_string()),
+        };
+
+        let jar = remove_session_cookies(CookieJar::new(), &config);
+        let response = (jar, ()).into_response();
+
+        let removed: Vec<&str> = response
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect();
+        assert_eq!(removed.len(), 3);
+        for cookie in removed {
+            assert!(cookie.contains("Max-Age=0"));
+            assert!(cookie.contains("Domain=example.com"));
+        }
+    }
+
+    #[test]
+    fn test_refresh_token_from_cookie_requires_csrf() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "cookie",
+            "refresh_token=refresh; csrf_token=abc".parse().unwrap(),
+        );
+        let (status, _) = refresh_token(None, &headers).unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        headers.insert("x-csrf-token", "abc".parse().unwrap());
+        assert_eq!(refresh_token(None, &headers).unwrap(), "refresh");
+        assert_eq!(
+            refresh_token(Some("body".to_string()), &headers).unwrap(),
+            "body"
+        );
+    }
+}
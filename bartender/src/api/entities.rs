@@ -1,5 +1,9 @@
+use chrono::{DateTime, Utc};
+use models::personal_token::PersonalTokenModel;
+use models::session::SessionModel;
 use serde::Serialize;
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 #[derive(Serialize, ToSchema)]
 pub struct AccessTokens {
@@ -7,10 +11,106 @@ pub struct AccessTokens {
     pub refresh_token: String,
     pub token_type: String,
     pub expires_in: u64,
+    pub scope: String,
 }
 
-#[derive(Serialize, ToSchema)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub message: String,
     pub details: Option<serde_json::Value>,
 }
+
+/// Personal access token. Its value is only returned once, on creation.
+#[derive(Serialize, ToSchema)]
+pub struct PersonalTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub scope: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<PersonalTokenModel> for PersonalTokenResponse {
+    fn from(model: PersonalTokenModel) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            scope: model.scopes.join(" "),
+            expires_at: model.expires_at,
+            last_used_at: model.last_used_at,
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedPersonalToken {
+    /// Send as `Authorization: Bearer <token>`. Store it now, it can't be shown again
+    pub token: String,
+    #[serde(flatten)]
+    pub personal_token: PersonalTokenResponse,
+}
+
+/// Device the user is logged in on.
+#[derive(Serialize, ToSchema)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Last login or refresh
+    pub last_used_at: DateTime<Utc>,
+}
+
+impl From<SessionModel> for SessionResponse {
+    fn from(model: SessionModel) -> Self {
+        Self {
+            id: model.id,
+            user_agent: model.user_agent,
+            ip_address: model.ip_address,
+            created_at: model.created_at,
+            last_used_at: model.last_used_at,
+        }
+    }
+}
+
+/// Token of a service client. There is no refresh token: the client authenticates again.
+#[derive(Serialize, ToSchema)]
+pub struct ServiceToken {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+    pub scope: String,
+}
+
+/// Answer of login: the tokens, or a challenge when the user has two-factor authentication.
+/// `status` tells which, `tokens` or `mfa_required`.
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LoginResponse {
+    Tokens(AccessTokens),
+    MfaRequired(MfaChallenge),
+}
+
+/// Exchanged for the tokens at `/api/auth/mfa/verify`, together with a code.
+#[derive(Serialize, ToSchema)]
+pub struct MfaChallenge {
+    pub mfa_token: String,
+    pub expires_in: u64,
+}
+
+/// Pending TOTP secret. It is asked for on login once confirmed with a code.
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrollment {
+    /// Base32 encoded, for authenticator apps that can't scan the URI
+    pub secret: String,
+    /// `otpauth://` URI, usually shown as a QR code
+    pub otpauth_uri: String,
+}
+
+/// One-time codes that replace a TOTP code, e.g. when the device is lost. Only shown once.
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
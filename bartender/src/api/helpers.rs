@@ -1,13 +1,34 @@
+use crate::api::client::ClientInfo;
 use crate::api::entities::{AccessTokens, ErrorResponse};
 use crate::app::AppState;
+use crate::config::UnverifiedEmailPolicy;
+use anyhow::anyhow;
+use auth::claims::Claims;
+use auth::hashing::hash_token;
+use auth::AuthenticatedUser;
 use axum::http::StatusCode;
 use axum::Json;
-use chrono::Duration;
+use chrono::{Duration, Utc};
+use log::{error, info};
+use models::service_client::ServiceClientModel;
 use models::user::User;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use regex::Regex;
 use std::sync::Arc;
+use uuid::Uuid;
 use validator::{Validate, ValidationError, ValidationErrors};
 
+pub fn internal_error() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            message: "Internal server error".to_string(),
+            details: None,
+        }),
+    )
+}
+
 pub fn validate_payload<T: Validate>(payload: &T) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
     if let Err(validation_error) = payload.validate() {
         Err((
@@ -54,14 +75,218 @@ pub fn validate_password(password: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
-pub fn generate_tokens(
+/// Login accepts a username or an email, so usernames can't look like an email.
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    if username.contains('@') {
+        let mut error = ValidationError::new("username_at");
+        error.message = Some("Username must not contain '@'".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Scopes granted for a token request: `requested` narrowed down from `allowed`,
+/// or everything in `allowed` when nothing was requested.
+pub fn narrow_scopes(
+    allowed: &[String],
+    requested: Option<&str>,
+) -> Result<Vec<String>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(requested) = requested else {
+        return Ok(allowed.to_vec());
+    };
+
+    let mut scopes: Vec<String> = Vec::new();
+    for scope in requested.split_whitespace() {
+        if !allowed.iter().any(|allowed| allowed == scope) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    message: format!("Invalid scope: {}", scope),
+                    details: None,
+                }),
+            ));
+        }
+        if !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_string());
+        }
+    }
+    Ok(scopes)
+}
+
+pub async fn ensure_not_revoked(
+    state: &Arc<AppState>,
+    claims: &Claims,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match state.token_manager.is_revoked(claims).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                message: "Token has been revoked".to_string(),
+                details: None,
+            }),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                message: "Failed to check token revocation".to_string(),
+                details: None,
+            }),
+        )),
+    }
+}
+
+/// Rejects tokens of deleted or disabled users when `check_active_users` is enabled.
+pub async fn ensure_user_active(
+    state: &Arc<AppState>,
+    user_id: &str,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match state.token_manager.is_user_active(user_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                message: "User no longer exists or is disabled".to_string(),
+                details: None,
+            }),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                message: "Failed to check user".to_string(),
+                details: None,
+            }),
+        )),
+    }
+}
+
+/// Disabled users can't get new tokens, whatever `check_active_users` says.
+pub async fn ensure_not_disabled(
+    state: &Arc<AppState>,
+    user: &User,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match state.auth_repository.is_active(user.id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                message: "User is disabled".to_string(),
+                details: None,
+            }),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                message: "Failed to check user".to_string(),
+                details: None,
+            }),
+        )),
+    }
+}
+
+/// Hashes `password` with `password_hasher` on the blocking thread pool, as hashing takes
+/// too long to run on the async runtime.
+pub async fn hash_password(state: &Arc<AppState>, password: &str) -> anyhow::Result<String> {
+    let hasher = state.password_hasher.clone();
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hasher.hash(&password))
+        .await
+        .map_err(|err| anyhow!("Password hashing failed: {}", err))?
+}
+
+/// Whether `password` matches `hash`, verified on the blocking thread pool. Without a hash,
+/// i.e. for unknown logins and clients, a dummy hash is verified so that the response
+/// doesn't tell whether they exist.
+pub async fn verify_password(state: &Arc<AppState>, password: &str, hash: Option<&str>) -> bool {
+    let state = state.clone();
+    let password = password.to_string();
+    let hash = hash.map(str::to_string);
+    tokio::task::spawn_blocking(move || match hash {
+        Some(hash) => state.password_hasher.verify(&password, &hash),
+        None => {
+            state
+                .password_hasher
+                .verify(&password, state.dummy_password_hash());
+            false
+        }
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Replaces a hash of another algorithm or parameters, e.g. bcrypt of earlier versions, once
+/// `password` is verified. The login goes on when that fails, the hash still verifies.
+pub async fn upgrade_password_hash(state: &Arc<AppState>, user: &User, password: &str) {
+    if !state.password_hasher.needs_rehash(&user.password_hash) {
+        return;
+    }
+
+    let password_hash = match hash_password(state, password).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            error!("Failed to rehash password: {}", e);
+            return;
+        }
+    };
+    match state
+        .auth_repository
+        .set_password(user.id, &password_hash)
+        .await
+    {
+        Ok(_) => info!("Upgraded password hash of user {}", user.id),
+        Err(e) => error!("Failed to store rehashed password: {:?}", e),
+    }
+}
+
+/// Scopes of a login of `user` under the `email_verification` policy: unverified users are
+/// refused or get fewer scopes, until they verify their email and log in again.
+pub async fn apply_email_verification_policy(
     state: &Arc<AppState>,
     user: &User,
+    scopes: Vec<String>,
+) -> Result<Vec<String>, (StatusCode, Json<ErrorResponse>)> {
+    let policy = state.email_verification.policy;
+    if policy == UnverifiedEmailPolicy::Allow {
+        return Ok(scopes);
+    }
+
+    match state.auth_repository.is_email_verified(user.id).await {
+        Ok(true) => Ok(scopes),
+        Ok(false) if policy == UnverifiedEmailPolicy::Block => Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                message: "Email is not verified".to_string(),
+                details: None,
+            }),
+        )),
+        Ok(false) => Ok(scopes
+            .into_iter()
+            .filter(|scope| state.email_verification.scopes.contains(scope))
+            .collect()),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                message: "Failed to check user".to_string(),
+                details: None,
+            }),
+        )),
+    }
+}
+
+/// Issues an access token and a refresh token of the session `family_id`. The refresh token
+/// is stored hashed so that it can be used only once.
+pub async fn generate_tokens(
+    state: &Arc<AppState>,
+    user: &User,
+    scopes: &[String],
+    family_id: Uuid,
 ) -> Result<AccessTokens, (StatusCode, Json<ErrorResponse>)> {
     let access_token = state
         .token_manager
-        .generate_access_token(
+        .generate_session_access_token(
             user,
+            scopes,
+            &family_id.to_string(),
             Duration::seconds(state.token_manager.access_token_expiration as i64),
         )
         .map_err(|_| {
@@ -74,33 +299,167 @@ pub fn generate_tokens(
             )
         })?;
 
+    let refresh_token_expiration =
+        Duration::seconds(state.token_manager.refresh_token_expiration as i64);
+    let refresh_token_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                message: "Failed to generate refresh token".to_string(),
+                details: None,
+            }),
+        )
+    };
     let refresh_token = state
         .token_manager
-        .generate_refresh_token(
-            user,
-            Duration::seconds(state.token_manager.refresh_token_expiration as i64),
+        .generate_refresh_token(user, scopes, refresh_token_expiration)
+        .map_err(|_| refresh_token_error())?;
+    state
+        .refresh_token_repository
+        .create(
+            user.id,
+            family_id,
+            &hash_token(&refresh_token),
+            Utc::now() + refresh_token_expiration,
         )
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    message: "Failed to generate refresh token".to_string(),
-                    details: None,
-                }),
-            )
-        })?;
+        .await
+        .map_err(|_| refresh_token_error())?;
 
     Ok(AccessTokens {
         access_token,
         refresh_token,
         token_type: "Bearer".to_string(),
         expires_in: state.token_manager.access_token_expiration,
+        scope: scopes.join(" "),
     })
 }
 
+/// Starts a session of `user` on the device of `client` and issues its first tokens.
+pub async fn start_session(
+    state: &Arc<AppState>,
+    client: &ClientInfo,
+    user: &User,
+    scopes: &[String],
+) -> Result<AccessTokens, (StatusCode, Json<ErrorResponse>)> {
+    let session_id = state
+        .session_repository
+        .create(
+            user.id,
+            client.user_agent.as_deref(),
+            client.ip_address.as_deref(),
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    generate_tokens(state, user, scopes, session_id).await
+}
+
+/// Rejects the access tokens of the given sessions, whose refresh tokens were just revoked.
+pub async fn revoke_sessions(
+    state: &Arc<AppState>,
+    session_ids: &[Uuid],
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    for session_id in session_ids {
+        state
+            .token_manager
+            .revoke_session(&session_id.to_string())
+            .await
+            .map_err(|_| internal_error())?;
+    }
+    Ok(())
+}
+
+/// Ends every session of the user but the one of `user`'s token, e.g. after a change of
+/// credentials. A personal access token has no session, all sessions end then.
+pub async fn end_other_sessions(
+    state: &Arc<AppState>,
+    user: &AuthenticatedUser,
+    user_id: Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let session_id = user
+        .session_id
+        .as_deref()
+        .and_then(|session_id| Uuid::parse_str(session_id).ok());
+    let revoked = match session_id {
+        Some(session_id) => {
+            state
+                .refresh_token_repository
+                .revoke_all_except(user_id, session_id)
+                .await
+        }
+        None => state.refresh_token_repository.revoke_all(user_id).await,
+    };
+    let families = revoked.map_err(|_| internal_error())?;
+    revoke_sessions(state, &families).await
+}
+
+/// Random single-use token, e.g. sent by email. Stored hashed with `hash_token`.
+pub fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+/// Id of the authenticated user. Tokens carry it as a string in `sub`. Service clients are
+/// rejected: their `sub` is a client id, which may well parse as a uuid too.
+pub fn user_id(user: &AuthenticatedUser) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+    if user.is_service() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                message: "Not available to service clients".to_string(),
+                details: None,
+            }),
+        ));
+    }
+    Uuid::parse_str(&user.id).map_err(|_| {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                message: "Invalid token subject".to_string(),
+                details: None,
+            }),
+        )
+    })
+}
+
+pub fn invalid_client() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(ErrorResponse {
+            message: "Invalid client credentials".to_string(),
+            details: None,
+        }),
+    )
+}
+
+/// Registered service client with the given credentials. Secrets are hashed like passwords,
+/// bcrypt hashes of earlier versions still verify.
+pub async fn authenticate_client(
+    state: &Arc<AppState>,
+    client_id: &str,
+    client_secret: &str,
+) -> Result<ServiceClientModel, (StatusCode, Json<ErrorResponse>)> {
+    let client = match state.service_client_repository.find(client_id).await {
+        Ok(Some(client)) => client,
+        Ok(None) => {
+            verify_password(state, client_secret, None).await;
+            return Err(invalid_client());
+        }
+        Err(_) => return Err(internal_error()),
+    };
+    if !verify_password(state, client_secret, Some(&client.secret_hash)).await {
+        return Err(invalid_client());
+    }
+    Ok(client)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use auth::claims::Principal;
     use axum::http::StatusCode;
     use serde_json::Value;
     use validator::Validate;
@@ -175,4 +534,74 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_validate_username() {
+        assert!(validate_username("alice").is_ok());
+
+        let err = validate_username("alice@example.com").unwrap_err();
+        assert_eq!(err.code, "username_at");
+    }
+
+    // ---------------------
+    // 3. narrow_scopes
+    // ---------------------
+
+    fn allowed() -> Vec<String> {
+        vec!["tasks:read".to_string(), "tasks:write".to_string()]
+    }
+
+    #[test]
+    fn test_narrow_scopes_defaults_to_allowed() {
+        let scopes = narrow_scopes(&allowed(), None).unwrap();
+        assert_eq!(scopes, allowed());
+    }
+
+    #[test]
+    fn test_narrow_scopes_ok() {
+        let scopes = narrow_scopes(&allowed(), Some("tasks:read  tasks:read")).unwrap();
+        assert_eq!(scopes, vec!["tasks:read".to_string()]);
+    }
+
+    #[test]
+    fn test_narrow_scopes_err() {
+        let result = narrow_scopes(&allowed(), Some("tasks:read admin"));
+
+        match result {
+            Ok(_) => panic!("Expected invalid scope error but returned OK"),
+            Err((status, json)) => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(json.0.message, "Invalid scope: admin");
+            }
+        }
+    }
+
+    // ---------------------
+    // 4. user_id
+    // ---------------------
+
+    fn authenticated(id: &str, principal: Principal) -> AuthenticatedUser {
+        AuthenticatedUser {
+            id: id.to_string(),
+            roles: vec![],
+            scopes: vec![],
+            principal,
+            session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_user_id_ok() {
+        let id = Uuid::new_v4();
+        let user = authenticated(&id.to_string(), Principal::User);
+        assert_eq!(user_id(&user).unwrap(), id);
+    }
+
+    #[test]
+    fn test_user_id_rejects_service_clients() {
+        let user = authenticated(&Uuid::new_v4().to_string(), Principal::Service);
+
+        let (status, _) = user_id(&user).unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
 }
@@ -1,9 +1,16 @@
 mod payload;
 mod bartender;
+mod client;
 mod helpers;
+mod cookies;
 mod entities;
+mod throttle;
+#[cfg(test)]
+mod test_util;
 
 use crate::app::AppState;
+use auth::layer::AuthLayer;
+use axum::routing::get;
 use axum::Router;
 use std::sync::Arc;
 use utoipa::OpenApi;
@@ -15,7 +22,27 @@ use utoipa_scalar::{Scalar, Servable};
         bartender::register,
         bartender::login,
         bartender::refresh,
-        bartender::validate
+        bartender::logout,
+        bartender::logout_all,
+        bartender::enroll_totp,
+        bartender::confirm_totp,
+        bartender::disable_totp,
+        bartender::verify_mfa,
+        bartender::change_password,
+        bartender::request_password_reset,
+        bartender::confirm_password_reset,
+        bartender::verify_email,
+        bartender::resend_verification_email,
+        bartender::revoke,
+        bartender::token,
+        bartender::validate,
+        bartender::introspect,
+        bartender::create_personal_token,
+        bartender::list_personal_tokens,
+        bartender::delete_personal_token,
+        bartender::list_sessions,
+        bartender::delete_session,
+        bartender::jwks
     ),
     tags(
         (name = "Bartender", description = "Authentication service"),
@@ -24,10 +51,31 @@ use utoipa_scalar::{Scalar, Servable};
 struct ApiDoc;
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
-    let api_router = Router::new().nest("/api/auth", bartender::router());
+    let api_router = Router::new()
+        .nest("/api/auth", bartender::router())
+        .route("/.well-known/jwks.json", get(bartender::jwks));
+
+    let auth_layer = AuthLayer::new(app_state.token_manager.clone())
+        .public_path("/docs")
+        .public_path("/.well-known/jwks.json")
+        .public_path("/api/auth/register")
+        .public_path("/api/auth/login")
+        .public_path("/api/auth/refresh")
+        .public_path("/api/auth/logout")
+        .public_path("/api/auth/mfa/verify")
+        .public_path("/api/auth/password-reset")
+        .public_path("/api/auth/password-reset/confirm")
+        .public_path("/api/auth/verify-email")
+        .public_path("/api/auth/verify-email/resend")
+        .public_path("/api/auth/revoke")
+        .public_path("/api/auth/token")
+        .public_path("/api/auth/validate")
+        // Authenticated with service client credentials by the handler
+        .public_path("/api/auth/introspect");
 
     Router::new()
         .merge(Scalar::with_url("/docs", ApiDoc::openapi()))
         .merge(api_router)
+        .layer(auth_layer)
         .layer(axum::Extension(app_state))
 }
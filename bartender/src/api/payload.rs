@@ -1,6 +1,6 @@
-use crate::api::helpers::validate_password;
-use models::user::User;
-use bcrypt::{hash, DEFAULT_COST};
+use crate::api::helpers::{validate_password, validate_username};
+use crate::passwords::PasswordHasher;
+use models::user::{User, ROLE_USER};
 use serde::Deserialize;
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -9,6 +9,7 @@ use validator::Validate;
 #[derive(Deserialize, ToSchema, Validate)]
 pub struct RegisterPayload {
     #[validate(length(min = 3, message = "Username must be at least 3 characters long"))]
+    #[validate(custom(function = "validate_username"))]
     username: String,
 
     #[validate(email(message = "Invalid email format"))]
@@ -19,31 +20,142 @@ pub struct RegisterPayload {
     password: String,
 }
 
-impl TryFrom<RegisterPayload> for User {
-    type Error = String;
+impl RegisterPayload {
+    pub fn into_user(self, hasher: &dyn PasswordHasher) -> Result<User, String> {
+        let password_hash = hasher.hash(&self.password).map_err(|e| e.to_string())?;
 
-    fn try_from(payload: RegisterPayload) -> Result<Self, Self::Error> {
-        let password_hash = hash(payload.password, DEFAULT_COST)
-            .map_err(|e| format!("Failed to hash password: {}", e))?;
-
-        Ok(Self {
+        Ok(User {
             id: Uuid::new_v4(),
-            username: payload.username,
-            email: payload.email,
+            username: self.username,
+            email: self.email,
             password_hash,
+            roles: vec![ROLE_USER.to_string()],
         })
     }
 }
 
 #[derive(Deserialize, ToSchema, Validate)]
 pub struct LoginPayload {
-    #[validate(length(min = 3, message = "Username must be at least 3 characters long"))]
-    pub username: String,
+    /// Username or email, in any case
+    #[serde(alias = "username")]
+    #[validate(length(min = 3, message = "Login must be at least 3 characters long"))]
+    pub login: String,
     pub password: String,
+    /// Space-separated scopes to narrow the token to. All scopes are granted when omitted
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ChangePasswordPayload {
+    pub current_password: String,
+
+    #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct PasswordResetPayload {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ConfirmPasswordResetPayload {
+    /// Token from the password reset email
+    #[validate(length(min = 1, message = "Token must be provided"))]
+    pub token: String,
+
+    #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct VerifyEmailPayload {
+    /// Token from the verification email
+    #[validate(length(min = 1, message = "Token must be provided"))]
+    pub token: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ResendVerificationPayload {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
 }
 
 #[derive(Deserialize, ToSchema, Validate)]
 pub struct RefreshPayload {
+    /// Taken from the refresh token cookie when omitted
     #[validate(length(min = 1, message = "Refresh token must be provider"))]
-    pub refresh_token: String,
+    pub refresh_token: Option<String>,
+    /// Space-separated scopes to narrow the new tokens to. Can't exceed the original grant
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct RevokePayload {
+    #[validate(length(min = 1, message = "Token must be provided"))]
+    pub token: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct LogoutPayload {
+    /// Refresh token of the session to end. Taken from the refresh token cookie when omitted
+    #[validate(length(min = 1, message = "Refresh token must be provided"))]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct IntrospectPayload {
+    pub token: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct CreatePersonalTokenPayload {
+    /// Name telling the token apart, e.g. the CI job using it
+    #[validate(length(min = 1, max = 100, message = "Name must be 1 to 100 characters long"))]
+    pub name: String,
+    /// Space-separated scopes of the token. Scopes of the current token when omitted
+    pub scope: Option<String>,
+    /// Lifetime in seconds, at most 10 years. The token never expires when omitted
+    #[validate(range(min = 1, max = 315_360_000, message = "Expiration must be between 1 second and 10 years"))]
+    pub expires_in: Option<u64>,
+}
+
+/// Client-credentials grant (RFC 6749, section 4.4). The client may authenticate with HTTP
+/// Basic instead of `client_id` and `client_secret`.
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ClientCredentialsPayload {
+    /// Must be `client_credentials`
+    pub grant_type: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// Space-separated scopes to narrow the token to. All scopes of the client when omitted
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ConfirmTotpPayload {
+    /// Current code of the authenticator app
+    #[validate(length(min = 1, message = "Code must be provided"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct DisableTotpPayload {
+    pub password: String,
+    /// Code of the authenticator app or a recovery code
+    #[validate(length(min = 1, message = "Code must be provided"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct VerifyMfaPayload {
+    /// Token returned by login
+    #[validate(length(min = 1, message = "Token must be provided"))]
+    pub mfa_token: String,
+    /// Code of the authenticator app or a recovery code
+    #[validate(length(min = 1, message = "Code must be provided"))]
+    pub code: String,
 }
@@ -0,0 +1,57 @@
+//! State and users for handler tests that need the database. Such tests use `#[sqlx::test]`,
+//! which runs them in a fresh database with the migrations applied, on the Postgres server of
+//! `DATABASE_URL`. They are `#[ignore]`d, run them with `cargo test -- --ignored`.
+
+use crate::api::client::ClientInfo;
+use crate::app::AppState;
+use crate::passwords::PasswordHashingConfig;
+use auth::revocation::InMemoryRevocationStore;
+use auth::test_util::test_token_manager;
+use models::user::{User, UserModel, ROLE_USER};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const TEST_PASSWORD: &str = "Password123!";
+
+pub fn test_state(pool: PgPool) -> Arc<AppState> {
+    let token_manager = test_token_manager().with_revocation_store(
+        Arc::new(InMemoryRevocationStore::new()),
+        Duration::from_secs(60),
+    );
+    // Cheapest Argon2id parameters, the defaults take seconds in debug builds
+    let password_hashing = PasswordHashingConfig {
+        memory_cost: 8,
+        time_cost: 1,
+        parallelism: 1,
+    };
+    let state = AppState::new(
+        pool,
+        token_manager,
+        vec!["tasks:read".to_string(), "tasks:write".to_string()],
+    )
+    .with_password_hasher(password_hashing.hasher().unwrap());
+    Arc::new(state)
+}
+
+/// User with `TEST_PASSWORD`.
+pub async fn create_test_user(state: &AppState, username: &str) -> User {
+    let user = User {
+        id: Uuid::new_v4(),
+        username: username.to_string(),
+        email: format!("{}@example.com", username),
+        password_hash: state.password_hasher.hash(TEST_PASSWORD).unwrap(),
+        roles: vec![ROLE_USER.to_string()],
+    };
+    let model = UserModel::from(user);
+    state.auth_repository.create(&model).await.unwrap();
+    User::from(model)
+}
+
+pub fn test_client() -> ClientInfo {
+    ClientInfo {
+        user_agent: Some("test".to_string()),
+        ip_address: Some("127.0.0.1".to_string()),
+    }
+}
@@ -0,0 +1,252 @@
+use crate::api::entities::ErrorResponse;
+use crate::api::helpers::internal_error;
+use crate::app::AppState;
+use crate::config::ThrottlePolicy;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, warn};
+use repository::login_attempts::{AttemptScope, Reservation};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// What failed logins are counted against: the client IP, when known, and the account.
+pub struct LoginAttempt {
+    ip: Option<String>,
+    account: Option<String>,
+}
+
+impl LoginAttempt {
+    /// `user_id` is `None` for unknown logins. Those are counted by login, so that a
+    /// lockout doesn't tell whether an account exists.
+    pub fn new(ip: Option<&str>, login: &str, user_id: Option<&str>) -> Self {
+        let account = match user_id {
+            Some(user_id) => format!("user:{}", user_id),
+            None => format!("login:{}", login.to_lowercase()),
+        };
+        Self {
+            ip: ip.map(str::to_string),
+            account: Some(account),
+        }
+    }
+
+    /// Attempt of a known user, e.g. a code of two-factor authentication.
+    pub fn for_user(ip: Option<&str>, user_id: Uuid) -> Self {
+        Self::new(ip, "", Some(&user_id.to_string()))
+    }
+
+    /// Password reset emailed to `email`. Requests are counted apart from logins, so that
+    /// they can't lock a user out of their account.
+    pub fn password_reset(ip: Option<&str>, email: &str) -> Self {
+        Self {
+            ip: ip.map(|ip| format!("reset:{}", ip)),
+            account: Some(format!("reset:{}", email.to_lowercase())),
+        }
+    }
+
+    /// Reset token tried by the client IP. The account is only known once the token is.
+    pub fn password_reset_confirmation(ip: Option<&str>) -> Self {
+        Self {
+            ip: ip.map(|ip| format!("reset-confirm:{}", ip)),
+            account: None,
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = (AttemptScope, &str)> {
+        let ip = self.ip.as_deref().map(|ip| (AttemptScope::Ip, ip));
+        let account = self
+            .account
+            .as_deref()
+            .map(|account| (AttemptScope::Account, account));
+        ip.into_iter().chain(account)
+    }
+}
+
+fn policy(state: &AppState, scope: AttemptScope) -> ThrottlePolicy {
+    match scope {
+        AttemptScope::Ip => state.login_throttle.ip,
+        AttemptScope::Account => state.login_throttle.account,
+    }
+}
+
+/// Login attempt counted as failed by `reserve_attempt`, until `clear_failed_logins` takes
+/// it back.
+pub struct ReservedAttempt {
+    reserved: Vec<(AttemptScope, String, Option<DateTime<Utc>>)>,
+}
+
+/// Counts the attempt as failed before the password or code is checked, and refuses it while
+/// the client IP or the account is locked out. The lockout a failure leads to starts right
+/// away, so concurrent attempts can't all get in under the limit.
+pub async fn reserve_attempt(
+    state: &Arc<AppState>,
+    attempt: &LoginAttempt,
+) -> Result<ReservedAttempt, (StatusCode, Json<ErrorResponse>)> {
+    let mut reserved = ReservedAttempt {
+        reserved: Vec::new(),
+    };
+    for (scope, key) in attempt.keys() {
+        let policy = policy(state, scope);
+        let reservation = state
+            .login_attempt_repository
+            .reserve(scope, key, policy.reset_after as i64, |failures| {
+                let delay = policy.delay(failures.max(0) as u32)?;
+                Some(Utc::now() + Duration::seconds(delay as i64))
+            })
+            .await;
+        match reservation {
+            Ok(Reservation::Reserved {
+                failures,
+                locked_until,
+            }) => {
+                if let Some(until) = locked_until {
+                    warn!(
+                        "Login of {:?} {} locked until {} after {} failures",
+                        scope, key, until, failures
+                    );
+                }
+                reserved
+                    .reserved
+                    .push((scope, key.to_string(), locked_until));
+            }
+            Ok(Reservation::Locked { until }) => {
+                // Refused attempts don't count
+                release_attempt(state, reserved).await;
+                let retry_after = (until - Utc::now()).num_seconds().max(1);
+                return Err((
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(ErrorResponse {
+                        message: "Too many failed attempts".to_string(),
+                        details: Some(json!({ "retry_after": retry_after })),
+                    }),
+                ));
+            }
+            Err(e) => {
+                error!("Failed to record login attempt: {}", e);
+                release_attempt(state, reserved).await;
+                return Err(internal_error());
+            }
+        }
+    }
+    Ok(reserved)
+}
+
+/// Takes the attempt back without clearing earlier failures, e.g. when the password is right
+/// but the second factor is still to be checked.
+pub async fn release_attempt(state: &Arc<AppState>, reserved: ReservedAttempt) {
+    for (scope, key, locked_until) in reserved.reserved {
+        if let Err(e) = state
+            .login_attempt_repository
+            .release(scope, &key, locked_until)
+            .await
+        {
+            error!("Failed to release login attempt: {}", e);
+        }
+    }
+}
+
+/// A successful login clears the failures of the account. Only the attempt itself is taken
+/// back from those of the IP, or an attacker could reset them by logging into an account
+/// of their own.
+pub async fn clear_failed_logins(state: &Arc<AppState>, attempt: ReservedAttempt) {
+    for (scope, key, locked_until) in attempt.reserved {
+        let repository = &state.login_attempt_repository;
+        let cleared = match scope {
+            AttemptScope::Account => repository.clear(scope, &key).await,
+            AttemptScope::Ip => repository.release(scope, &key, locked_until).await,
+        };
+        if let Err(e) = cleared {
+            error!("Failed to clear login attempts: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::test_state;
+    use crate::config::LoginThrottleConfig;
+    use sqlx::PgPool;
+
+    #[test]
+    fn test_login_attempt_keys() {
+        let attempt = LoginAttempt::new(Some("10.0.0.1"), "Alice", Some("42"));
+        let keys: Vec<_> = attempt.keys().collect();
+        assert_eq!(
+            keys,
+            vec![
+                (AttemptScope::Ip, "10.0.0.1"),
+                (AttemptScope::Account, "user:42")
+            ]
+        );
+
+        let attempt = LoginAttempt::new(None, "Alice@Example.com", None);
+        let keys: Vec<_> = attempt.keys().collect();
+        assert_eq!(
+            keys,
+            vec![(AttemptScope::Account, "login:alice@example.com")]
+        );
+
+        let attempt = LoginAttempt::password_reset(Some("10.0.0.1"), "Alice@Example.com");
+        let keys: Vec<_> = attempt.keys().collect();
+        assert_eq!(
+            keys,
+            vec![
+                (AttemptScope::Ip, "reset:10.0.0.1"),
+                (AttemptScope::Account, "reset:alice@example.com")
+            ]
+        );
+
+        let attempt = LoginAttempt::password_reset_confirmation(Some("10.0.0.1"));
+        let keys: Vec<_> = attempt.keys().collect();
+        assert_eq!(keys, vec![(AttemptScope::Ip, "reset-confirm:10.0.0.1")]);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_concurrent_attempts_stop_at_the_lockout(pool: PgPool) {
+        let policy = ThrottlePolicy {
+            free_attempts: 2,
+            base_delay: 60,
+            max_delay: 60,
+            reset_after: 3600,
+        };
+        let state = Arc::into_inner(test_state(pool))
+            .unwrap()
+            .with_login_throttle(LoginThrottleConfig {
+                account: policy,
+                ip: policy,
+            });
+        let state = Arc::new(state);
+        let attempt = Arc::new(LoginAttempt::new(Some("10.0.0.1"), "alice", None));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let (state, attempt) = (state.clone(), attempt.clone());
+                tokio::spawn(async move { reserve_attempt(&state, &attempt).await.is_ok() })
+            })
+            .collect();
+        let mut reserved = 0;
+        for handle in handles {
+            reserved += handle.await.unwrap() as usize;
+        }
+
+        // The third failure starts the lockout
+        assert_eq!(reserved, 3);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_successful_attempts_are_taken_back(pool: PgPool) {
+        let state = test_state(pool);
+        let attempt = LoginAttempt::new(Some("10.0.0.1"), "alice", None);
+        let free_attempts = state.login_throttle.account.free_attempts;
+
+        for _ in 0..free_attempts * 2 {
+            let reserved = reserve_attempt(&state, &attempt).await.unwrap();
+            clear_failed_logins(&state, reserved).await;
+        }
+        assert!(reserve_attempt(&state, &attempt).await.is_ok());
+    }
+}
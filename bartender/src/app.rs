@@ -1,19 +1,156 @@
 use sqlx::PgPool;
-use std::sync::Arc;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+use uuid::Uuid;
 use auth::tokens::TokenManager;
 use repository::auth::AuthRepository;
+use repository::email_verifications::EmailVerificationRepository;
+use repository::login_attempts::LoginAttemptRepository;
+use repository::mfa_challenges::MfaChallengeRepository;
+use repository::password_resets::PasswordResetRepository;
+use repository::personal_tokens::PersonalTokenRepository;
+use repository::refresh_tokens::RefreshTokenRepository;
+use repository::service_clients::ServiceClientRepository;
+use repository::sessions::SessionRepository;
+use repository::totp::TotpRepository;
+use crate::config::{
+    default_client_token_expiration, default_password_reset_expiration, EmailVerificationConfig,
+    LoginThrottleConfig, MfaConfig, SessionCookieConfig,
+};
+use crate::mailer::{DisabledMailer, Mailer};
+use crate::passwords::{Argon2idHasher, PasswordHasher};
 
 pub struct AppState {
     pub auth_repository: Arc<AuthRepository>,
+    pub email_verification_repository: Arc<EmailVerificationRepository>,
+    pub login_attempt_repository: Arc<LoginAttemptRepository>,
+    pub mfa_challenge_repository: Arc<MfaChallengeRepository>,
+    pub password_reset_repository: Arc<PasswordResetRepository>,
+    pub personal_token_repository: Arc<PersonalTokenRepository>,
+    pub refresh_token_repository: Arc<RefreshTokenRepository>,
+    pub service_client_repository: Arc<ServiceClientRepository>,
+    pub session_repository: Arc<SessionRepository>,
+    pub totp_repository: Arc<TotpRepository>,
     pub token_manager: Arc<TokenManager>,
+    pub scopes: Vec<String>,
+    pub session_cookies: Option<SessionCookieConfig>,
+    /// Lifetime in seconds of tokens issued to service clients
+    pub client_token_expiration: u64,
+    /// Lifetime in seconds of password reset tokens
+    pub password_reset_expiration: u64,
+    pub mailer: Arc<dyn Mailer>,
+    pub password_hasher: Arc<dyn PasswordHasher>,
+    /// Hash of `password_hasher` verified for unknown logins and clients, see
+    /// `dummy_password_hash`
+    dummy_password_hash: OnceLock<String>,
+    pub email_verification: EmailVerificationConfig,
+    pub login_throttle: LoginThrottleConfig,
+    /// Proxies whose `X-Forwarded-For` header is trusted for the client IP
+    pub trusted_proxies: Vec<IpAddr>,
+    pub mfa: MfaConfig,
 }
 
 impl AppState {
-    pub fn new(database_pool: PgPool, token_manager: TokenManager) -> Self {
+    pub fn new(database_pool: PgPool, token_manager: TokenManager, scopes: Vec<String>) -> Self {
         let database_pool = Arc::new(database_pool);
-        let auth_repository = Arc::new(AuthRepository::new(database_pool));
+        let auth_repository = Arc::new(AuthRepository::new(database_pool.clone()));
+        let email_verification_repository =
+            Arc::new(EmailVerificationRepository::new(database_pool.clone()));
+        let login_attempt_repository =
+            Arc::new(LoginAttemptRepository::new(database_pool.clone()));
+        let mfa_challenge_repository =
+            Arc::new(MfaChallengeRepository::new(database_pool.clone()));
+        let password_reset_repository =
+            Arc::new(PasswordResetRepository::new(database_pool.clone()));
+        let personal_token_repository =
+            Arc::new(PersonalTokenRepository::new(database_pool.clone()));
+        let refresh_token_repository =
+            Arc::new(RefreshTokenRepository::new(database_pool.clone()));
+        let service_client_repository =
+            Arc::new(ServiceClientRepository::new(database_pool.clone()));
+        let session_repository = Arc::new(SessionRepository::new(database_pool.clone()));
+        let totp_repository = Arc::new(TotpRepository::new(database_pool));
         let token_manager = Arc::new(token_manager);
 
-        Self { auth_repository, token_manager }
+        Self {
+            auth_repository,
+            email_verification_repository,
+            login_attempt_repository,
+            mfa_challenge_repository,
+            password_reset_repository,
+            personal_token_repository,
+            refresh_token_repository,
+            service_client_repository,
+            session_repository,
+            totp_repository,
+            token_manager,
+            scopes,
+            session_cookies: None,
+            client_token_expiration: default_client_token_expiration(),
+            password_reset_expiration: default_password_reset_expiration(),
+            mailer: Arc::new(DisabledMailer),
+            password_hasher: Arc::new(Argon2idHasher::default()),
+            dummy_password_hash: OnceLock::new(),
+            email_verification: EmailVerificationConfig::default(),
+            login_throttle: LoginThrottleConfig::default(),
+            trusted_proxies: Vec::new(),
+            mfa: MfaConfig::default(),
+        }
+    }
+
+    pub fn with_client_token_expiration(mut self, client_token_expiration: u64) -> Self {
+        self.client_token_expiration = client_token_expiration;
+        self
+    }
+
+    pub fn with_password_reset_expiration(mut self, password_reset_expiration: u64) -> Self {
+        self.password_reset_expiration = password_reset_expiration;
+        self
+    }
+
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    pub fn with_password_hasher(mut self, password_hasher: Arc<dyn PasswordHasher>) -> Self {
+        self.password_hasher = password_hasher;
+        self.dummy_password_hash = OnceLock::new();
+        self
+    }
+
+    pub fn with_email_verification(mut self, email_verification: EmailVerificationConfig) -> Self {
+        self.email_verification = email_verification;
+        self
+    }
+
+    pub fn with_login_throttle(mut self, login_throttle: LoginThrottleConfig) -> Self {
+        self.login_throttle = login_throttle;
+        self
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    pub fn with_mfa(mut self, mfa: MfaConfig) -> Self {
+        self.mfa = mfa;
+        self
+    }
+
+    pub fn with_session_cookies(mut self, session_cookies: Option<SessionCookieConfig>) -> Self {
+        self.session_cookies = session_cookies;
+        self
+    }
+
+    /// Hash of a password no one knows, with the parameters of new hashes. It is verified
+    /// when a login or client is unknown, so that it takes as long as a wrong password.
+    pub fn dummy_password_hash(&self) -> &str {
+        self.dummy_password_hash.get_or_init(|| {
+            self.password_hasher
+                .hash(&Uuid::new_v4().to_string())
+                .unwrap_or_default()
+        })
     }
 }
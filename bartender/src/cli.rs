@@ -1,5 +1,6 @@
 use std::path::PathBuf;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use jsonwebtoken::Algorithm;
 use multitool_hg::logger::tracer_logger::LogLevel;
 
 #[derive(Parser)]
@@ -18,6 +19,79 @@ pub struct Cli {
         value_enum
     )]
     pub log_level: LogLevel,
+
+    /// Runs an admin command instead of the server
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Makes a new JWT signing key active and keeps the current one for verification.
+    /// Restart running instances to pick up the new key.
+    RotateKey(RotateKeyArgs),
+    /// Grants a role to a user
+    GrantRole(RoleArgs),
+    /// Revokes a role from a user
+    RevokeRole(RoleArgs),
+    /// Disables a user: login fails and issued tokens stop working
+    DisableUser(UserArgs),
+    /// Enables a disabled user
+    EnableUser(UserArgs),
+    /// Registers a service client for the client-credentials grant and prints its secret
+    CreateClient(CreateClientArgs),
+    /// Deletes a service client. Its issued tokens work until they expire
+    DeleteClient(ClientArgs),
+}
+
+#[derive(Args)]
+pub struct CreateClientArgs {
+    /// Name of the service, e.g. billing
+    pub name: String,
+
+    /// Scope the client may request, repeat for several
+    #[arg(long = "scope")]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ClientArgs {
+    /// client_id of the service client
+    pub client_id: String,
+}
+
+#[derive(Args)]
+pub struct UserArgs {
+    /// Username of the user
+    pub username: String,
+}
+
+#[derive(Args)]
+pub struct RoleArgs {
+    /// Username of the user
+    pub username: String,
+
+    /// Role name, e.g. admin
+    pub role: String,
+}
+
+#[derive(Args)]
+pub struct RotateKeyArgs {
+    /// Key id written to the `kid` header, a random one by default
+    #[arg(long)]
+    pub kid: Option<String>,
+
+    /// Signing algorithm of the new key, the current one by default
+    #[arg(long)]
+    pub algorithm: Option<Algorithm>,
+
+    /// File with the new HMAC secret (HS256), `-` to read it from stdin
+    #[arg(long, value_name = "FILE")]
+    pub secret_file: Option<PathBuf>,
+
+    /// Path to the new PEM encoded private key (RS256 / EdDSA)
+    #[arg(long, value_name = "PEM_FILE")]
+    pub private_key: Option<PathBuf>,
 }
 
 impl Cli {
@@ -33,14 +107,15 @@ mod tests {
 
     #[test]
     fn test_default_arguments() {
-        let args = Cli::try_parse_from(&["test-app"]).unwrap();
+        let args = Cli::try_parse_from(["test-app"]).unwrap();
         assert_eq!(args.config, PathBuf::from("bartender.config.yaml"));
         assert_eq!(args.log_level, LogLevel::Info);
+        assert!(args.command.is_none());
     }
 
     #[test]
     fn test_custom_arguments() {
-        let args = Cli::try_parse_from(&[
+        let args = Cli::try_parse_from([
             "test-app",
             "--config", "custom_config.yaml",
             "--log-level", "debug"
@@ -49,4 +124,67 @@ mod tests {
         assert_eq!(args.config, PathBuf::from("custom_config.yaml"));
         assert_eq!(args.log_level, LogLevel::Debug);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_rotate_key_arguments() {
+        let args = Cli::try_parse_from([
+            "test-app",
+            "rotate-key",
+            "--kid", "2025-02",
+            "--algorithm", "EdDSA",
+            "--private-key", "keys/new.pem",
+            "--secret-file", "-"
+        ]).unwrap();
+
+        match args.command {
+            Some(Command::RotateKey(rotate)) => {
+                assert_eq!(rotate.kid.as_deref(), Some("2025-02"));
+                assert_eq!(rotate.algorithm, Some(Algorithm::EdDSA));
+                assert_eq!(rotate.private_key, Some(PathBuf::from("keys/new.pem")));
+                assert_eq!(rotate.secret_file, Some(PathBuf::from("-")));
+            }
+            _ => panic!("Expected rotate-key command"),
+        }
+    }
+
+    #[test]
+    fn test_grant_role_arguments() {
+        let args = Cli::try_parse_from(["test-app", "grant-role", "alice", "admin"]).unwrap();
+
+        match args.command {
+            Some(Command::GrantRole(grant)) => {
+                assert_eq!(grant.username, "alice");
+                assert_eq!(grant.role, "admin");
+            }
+            _ => panic!("Expected grant-role command"),
+        }
+    }
+
+    #[test]
+    fn test_disable_user_arguments() {
+        let args = Cli::try_parse_from(["test-app", "disable-user", "alice"]).unwrap();
+
+        match args.command {
+            Some(Command::DisableUser(disable)) => assert_eq!(disable.username, "alice"),
+            _ => panic!("Expected disable-user command"),
+        }
+    }
+
+    #[test]
+    fn test_create_client_arguments() {
+        let args = Cli::try_parse_from([
+            "test-app",
+            "create-client", "billing",
+            "--scope", "tasks:read",
+            "--scope", "tasks:write"
+        ]).unwrap();
+
+        match args.command {
+            Some(Command::CreateClient(create)) => {
+                assert_eq!(create.name, "billing");
+                assert_eq!(create.scopes, vec!["tasks:read", "tasks:write"]);
+            }
+            _ => panic!("Expected create-client command"),
+        }
+    }
+}
@@ -1,17 +1,406 @@
+use crate::mailer::MailerConfig;
+use crate::passwords::PasswordHashingConfig;
 use anyhow::anyhow;
+use auth::keys::JwtKey;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::Algorithm;
 use multitool_hg::database::config::DatabaseConfig;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppConfig {
     pub host: String,
     pub port: String,
+    /// Signing algorithm: HS256 uses `jwt_secret`, RS256 and EdDSA use `jwt_private_key`
+    #[serde(default)]
+    pub jwt_algorithm: Algorithm,
+    #[serde(default)]
     pub jwt_secret: String,
+    /// Path to the PEM encoded private key
+    pub jwt_private_key: Option<PathBuf>,
+    /// `kid` header of tokens signed with the active key
+    pub jwt_key_id: Option<String>,
+    /// File `bartender rotate-key` writes the signing keys to. Once it exists, its keys are
+    /// used instead of the `jwt_*` keys above
+    pub jwt_keyring: Option<PathBuf>,
+    /// `iss` claim of issued tokens
+    #[serde(default = "default_jwt_issuer")]
+    pub jwt_issuer: String,
+    /// `aud` claim of issued tokens, i.e. the services allowed to accept them
+    #[serde(default = "default_jwt_audience")]
+    pub jwt_audience: Vec<String>,
+    /// Allowed clock skew in seconds when checking `exp` and `nbf`
+    #[serde(default = "default_jwt_leeway")]
+    pub jwt_leeway: u64,
+    /// Scopes a token may be granted. Login without a `scope` gets all of them
+    #[serde(default = "default_jwt_scopes")]
+    pub jwt_scopes: Vec<String>,
+    /// Seconds a revocation lookup is cached for
+    #[serde(default = "default_revocation_cache_ttl")]
+    pub revocation_cache_ttl: u64,
+    /// Check on authenticated requests that the user still exists and is active
+    #[serde(default = "default_check_active_users")]
+    pub check_active_users: bool,
+    /// Seconds the result of an active user check is cached for
+    #[serde(default = "default_active_user_cache_ttl")]
+    pub active_user_cache_ttl: u64,
+    /// Also set the tokens as `HttpOnly` cookies on login and refresh, for browser clients
+    pub session_cookies: Option<SessionCookieConfig>,
     pub access_token_expiration: u64,
+    /// Lifetime in seconds of tokens issued to service clients. They get no refresh token
+    #[serde(default = "default_client_token_expiration")]
+    pub client_token_expiration: u64,
     pub refresh_token_expiration: u64,
+    /// Lifetime in seconds of password reset tokens
+    #[serde(default = "default_password_reset_expiration")]
+    pub password_reset_expiration: u64,
+    /// Argon2id parameters of new password hashes. Hashes with other parameters are
+    /// replaced on login
+    #[serde(default)]
+    pub password_hashing: PasswordHashingConfig,
+    /// How emails, e.g. password reset tokens, are delivered
+    #[serde(default)]
+    pub mailer: MailerConfig,
+    #[serde(default)]
+    pub email_verification: EmailVerificationConfig,
+    /// Backoff and lockout after failed logins
+    #[serde(default)]
+    pub login_throttle: LoginThrottleConfig,
+    /// Addresses of the reverse proxies in front of bartender. The client IP of their
+    /// requests is taken from `X-Forwarded-For`, or is unknown without one
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Two-factor authentication with TOTP
+    #[serde(default)]
+    pub mfa: MfaConfig,
+}
+
+fn default_jwt_issuer() -> String {
+    "bartender".to_string()
+}
+
+fn default_jwt_audience() -> Vec<String> {
+    vec!["todo".to_string()]
+}
+
+fn default_jwt_leeway() -> u64 {
+    60
+}
+
+fn default_jwt_scopes() -> Vec<String> {
+    vec!["tasks:read".to_string(), "tasks:write".to_string()]
+}
+
+fn default_revocation_cache_ttl() -> u64 {
+    30
+}
+
+fn default_check_active_users() -> bool {
+    true
+}
+
+fn default_active_user_cache_ttl() -> u64 {
+    30
+}
+
+pub(crate) fn default_client_token_expiration() -> u64 {
+    300
+}
+
+pub(crate) fn default_password_reset_expiration() -> u64 {
+    3600
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SessionCookieConfig {
+    #[serde(default)]
+    pub same_site: CookieSameSite,
+    /// `Domain` attribute, to share the cookies with services on subdomains
+    pub domain: Option<String>,
+}
+
+/// `SameSite=None` is not offered: it would send the cookies with every cross-site request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CookieSameSite {
+    Strict,
+    #[default]
+    Lax,
+}
+
+/// Emails are verified with a token mailed on registration.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmailVerificationConfig {
+    /// What users can do until they verify their email
+    #[serde(default)]
+    pub policy: UnverifiedEmailPolicy,
+    /// Scopes granted to unverified users with the `limit_scopes` policy
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Lifetime in seconds of verification tokens
+    #[serde(default = "default_email_verification_expiration")]
+    pub token_expiration: u64,
+}
+
+impl Default for EmailVerificationConfig {
+    fn default() -> Self {
+        Self {
+            policy: UnverifiedEmailPolicy::default(),
+            scopes: vec![],
+            token_expiration: default_email_verification_expiration(),
+        }
+    }
+}
+
+fn default_email_verification_expiration() -> u64 {
+    86400
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnverifiedEmailPolicy {
+    /// Same as verified users
+    #[default]
+    Allow,
+    /// Login is refused
+    Block,
+    /// Tokens only get the `scopes` of `EmailVerificationConfig`
+    LimitScopes,
+}
+
+/// Failed logins are counted per client IP and per account, in the database so that the
+/// limits hold across restarts and instances.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoginThrottleConfig {
+    #[serde(default = "default_account_throttle")]
+    pub account: ThrottlePolicy,
+    /// Clients behind one NAT share an IP, so it is allowed more failures
+    #[serde(default = "default_ip_throttle")]
+    pub ip: ThrottlePolicy,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            account: default_account_throttle(),
+            ip: default_ip_throttle(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ThrottlePolicy {
+    /// Failures allowed before logins are refused for a while
+    pub free_attempts: u32,
+    /// Seconds logins are refused after the first failure beyond `free_attempts`. The delay
+    /// doubles with every further failure
+    pub base_delay: u64,
+    /// Longest delay in seconds, i.e. the lockout
+    pub max_delay: u64,
+    /// Seconds without failures after which the count starts over
+    pub reset_after: u64,
+}
+
+impl ThrottlePolicy {
+    /// Seconds logins are refused after the given number of failures.
+    pub fn delay(&self, failures: u32) -> Option<u64> {
+        let exponent = failures.checked_sub(self.free_attempts + 1)?.min(63);
+        Some(self.base_delay.saturating_mul(1 << exponent).min(self.max_delay))
+    }
+}
+
+fn default_account_throttle() -> ThrottlePolicy {
+    ThrottlePolicy {
+        free_attempts: 5,
+        base_delay: 1,
+        max_delay: 900,
+        reset_after: 3600,
+    }
+}
+
+fn default_ip_throttle() -> ThrottlePolicy {
+    ThrottlePolicy {
+        free_attempts: 20,
+        ..default_account_throttle()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MfaConfig {
+    /// Issuer shown by authenticator apps next to the account
+    #[serde(default = "default_mfa_issuer")]
+    pub issuer: String,
+    /// Lifetime in seconds of the challenge token login returns when a code is required
+    #[serde(default = "default_mfa_challenge_expiration")]
+    pub challenge_expiration: u64,
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        Self {
+            issuer: default_mfa_issuer(),
+            challenge_expiration: default_mfa_challenge_expiration(),
+        }
+    }
+}
+
+fn default_mfa_issuer() -> String {
+    "bartender".to_string()
+}
+
+fn default_mfa_challenge_expiration() -> u64 {
+    300
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RetiredKeyConfig {
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub secret: String,
+    pub private_key: Option<PathBuf>,
+    pub retired_at: DateTime<Utc>,
+}
+
+impl AppConfig {
+    /// Keys of `jwt_keyring` once rotated, of the `jwt_*` settings before.
+    pub(crate) fn keyring(&self) -> anyhow::Result<Keyring> {
+        match &self.jwt_keyring {
+            Some(path) if path.exists() => Keyring::load(path),
+            _ => Ok(Keyring {
+                key_id: self.jwt_key_id.clone(),
+                algorithm: self.jwt_algorithm,
+                secret: self.jwt_secret.clone(),
+                private_key: self.jwt_private_key.clone(),
+                retired_keys: vec![],
+            }),
+        }
+    }
+}
+
+/// Signing keys managed by `bartender rotate-key`. They live in a file of their own, so
+/// that rotating doesn't rewrite the config.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Keyring {
+    pub key_id: Option<String>,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub secret: String,
+    pub private_key: Option<PathBuf>,
+    /// Replaced keys, kept to verify tokens issued before the rotation
+    #[serde(default)]
+    pub retired_keys: Vec<RetiredKeyConfig>,
+}
+
+impl Keyring {
+    fn load(file_path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(file_path)
+            .map_err(|err| anyhow!("Can't read {:?}: {}", file_path, err))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|err| anyhow!("Can't read yaml {:?}: {}", file_path, err))
+    }
+
+    pub(crate) fn save(&self, file_path: &Path) -> anyhow::Result<()> {
+        let contents = serde_yaml::to_string(self)
+            .map_err(|err| anyhow!("Can't write yaml {:?}: {}", file_path, err))?;
+        std::fs::write(file_path, contents)
+            .map_err(|err| anyhow!("Can't write {:?}: {}", file_path, err))
+    }
+
+    pub(crate) fn jwt_key(&self) -> anyhow::Result<JwtKey> {
+        load_jwt_key(
+            self.key_id.as_deref(),
+            self.algorithm,
+            &self.secret,
+            self.private_key.as_deref(),
+        )
+    }
+
+    pub(crate) fn jwt_retired_keys(&self) -> anyhow::Result<Vec<JwtKey>> {
+        self.retired_keys
+            .iter()
+            .map(|key| {
+                load_jwt_key(
+                    key.kid.as_deref(),
+                    key.algorithm,
+                    &key.secret,
+                    key.private_key.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    /// Makes the given key active and retires the current one.
+    ///
+    /// Retired keys older than `refresh_token_expiration` seconds can't have signed a valid
+    /// token anymore and are dropped.
+    pub(crate) fn rotate(
+        &mut self,
+        kid: String,
+        algorithm: Algorithm,
+        secret: String,
+        private_key: Option<PathBuf>,
+        now: DateTime<Utc>,
+        refresh_token_expiration: u64,
+    ) -> anyhow::Result<()> {
+        if self.key_id.as_deref() == Some(kid.as_str())
+            || self
+                .retired_keys
+                .iter()
+                .any(|key| key.kid.as_deref() == Some(kid.as_str()))
+        {
+            return Err(anyhow!("Key id {} is already in use", kid));
+        }
+        load_jwt_key(Some(&kid), algorithm, &secret, private_key.as_deref())?;
+
+        let retired = RetiredKeyConfig {
+            kid: self.key_id.replace(kid),
+            algorithm: std::mem::replace(&mut self.algorithm, algorithm),
+            secret: std::mem::replace(&mut self.secret, secret),
+            private_key: std::mem::replace(&mut self.private_key, private_key),
+            retired_at: now,
+        };
+        let expired_before = now - Duration::seconds(refresh_token_expiration as i64);
+        self.retired_keys
+            .retain(|key| key.retired_at > expired_before);
+        self.retired_keys.insert(0, retired);
+
+        Ok(())
+    }
+}
+
+fn load_jwt_key(
+    kid: Option<&str>,
+    algorithm: Algorithm,
+    secret: &str,
+    private_key: Option<&Path>,
+) -> anyhow::Result<JwtKey> {
+    let key = if algorithm == Algorithm::HS256 {
+        if secret.is_empty() {
+            return Err(anyhow!("jwt_secret is required for HS256"));
+        }
+        JwtKey::from_secret(secret.as_bytes())
+    } else {
+        let path = private_key
+            .ok_or_else(|| anyhow!("jwt_private_key is required for {:?}", algorithm))?;
+        let pem = std::fs::read(path).map_err(|err| anyhow!("Can't read {:?}: {}", path, err))?;
+        let key = match algorithm {
+            Algorithm::RS256 => JwtKey::from_rsa_pem(&pem),
+            Algorithm::EdDSA => JwtKey::from_ed_pem(&pem),
+            algorithm => return Err(anyhow!("Unsupported jwt_algorithm {:?}", algorithm)),
+        };
+        key.map_err(|err| anyhow!("Invalid private key {:?}: {}", path, err))?
+    };
+
+    Ok(match kid {
+        Some(kid) => key.with_kid(kid),
+        None => key,
+    })
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -32,3 +421,164 @@ impl BartenderConfig {
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "localhost".to_string(),
+            port: "3001".to_string(),
+            jwt_algorithm: Algorithm::HS256,
+            jwt_secret: "first".to_string(),
+            jwt_private_key: None,
+            jwt_key_id: None,
+            jwt_keyring: None,
+            jwt_issuer: default_jwt_issuer(),
+            jwt_audience: default_jwt_audience(),
+            jwt_leeway: default_jwt_leeway(),
+            jwt_scopes: default_jwt_scopes(),
+            revocation_cache_ttl: default_revocation_cache_ttl(),
+            check_active_users: default_check_active_users(),
+            active_user_cache_ttl: default_active_user_cache_ttl(),
+            session_cookies: None,
+            access_token_expiration: 3600,
+            client_token_expiration: default_client_token_expiration(),
+            refresh_token_expiration: 604800,
+            password_reset_expiration: default_password_reset_expiration(),
+            password_hashing: PasswordHashingConfig::default(),
+            mailer: MailerConfig::default(),
+            email_verification: EmailVerificationConfig::default(),
+            login_throttle: LoginThrottleConfig::default(),
+            trusted_proxies: Vec::new(),
+            mfa: MfaConfig::default(),
+        }
+    }
+
+    #[test]
+    fn test_throttle_delay() {
+        let policy = default_account_throttle();
+        assert_eq!(policy.delay(5), None);
+        assert_eq!(policy.delay(6), Some(1));
+        assert_eq!(policy.delay(7), Some(2));
+        assert_eq!(policy.delay(10), Some(16));
+        assert_eq!(policy.delay(15), Some(512));
+        assert_eq!(policy.delay(16), Some(900));
+        assert_eq!(policy.delay(u32::MAX), Some(900));
+    }
+
+    #[test]
+    fn test_login_throttle_config() {
+        let config: LoginThrottleConfig = serde_yaml::from_str(
+            "ip: { free_attempts: 50, base_delay: 2, max_delay: 60, reset_after: 600 }",
+        )
+        .unwrap();
+        assert_eq!(config.ip.free_attempts, 50);
+        assert_eq!(config.ip.delay(52), Some(4));
+        assert_eq!(config.account, default_account_throttle());
+    }
+
+    #[test]
+    fn test_email_verification_config() {
+        let config: EmailVerificationConfig =
+            serde_yaml::from_str("policy: limit_scopes\nscopes: [ tasks:read ]").unwrap();
+        assert_eq!(config.policy, UnverifiedEmailPolicy::LimitScopes);
+        assert_eq!(config.scopes, vec!["tasks:read".to_string()]);
+        assert_eq!(config.token_expiration, default_email_verification_expiration());
+
+        assert_eq!(
+            app_config().email_verification.policy,
+            UnverifiedEmailPolicy::Allow
+        );
+    }
+
+    fn keyring() -> Keyring {
+        app_config().keyring().unwrap()
+    }
+
+    fn rotate(keyring: &mut Keyring, kid: &str, now: DateTime<Utc>) -> anyhow::Result<()> {
+        keyring.rotate(
+            kid.to_string(),
+            Algorithm::HS256,
+            kid.to_string(),
+            None,
+            now,
+            604800,
+        )
+    }
+
+    #[test]
+    fn test_rotate_jwt_key() {
+        let mut keyring = keyring();
+        rotate(&mut keyring, "second", Utc::now()).unwrap();
+
+        assert_eq!(keyring.key_id.as_deref(), Some("second"));
+        assert_eq!(keyring.secret, "second");
+        assert_eq!(keyring.retired_keys.len(), 1);
+        assert_eq!(keyring.retired_keys[0].kid, None);
+        assert_eq!(keyring.retired_keys[0].secret, "first");
+        assert_eq!(keyring.jwt_key().unwrap().kid(), Some("second"));
+        assert_eq!(keyring.jwt_retired_keys().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rotate_jwt_key_drops_expired_keys() {
+        let mut keyring = keyring();
+        let now = Utc::now();
+        rotate(&mut keyring, "second", now).unwrap();
+        rotate(&mut keyring, "third", now + Duration::seconds(604801)).unwrap();
+
+        let kids: Vec<_> = keyring
+            .retired_keys
+            .iter()
+            .map(|key| key.kid.clone())
+            .collect();
+        assert_eq!(kids, vec![Some("second".to_string())]);
+    }
+
+    #[test]
+    fn test_rotate_jwt_key_rejects_invalid_key() {
+        let mut keyring = keyring();
+        let result = keyring.rotate(
+            "second".to_string(),
+            Algorithm::RS256,
+            String::new(),
+            None,
+            Utc::now(),
+            604800,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(keyring.secret, "first");
+        assert!(keyring.retired_keys.is_empty());
+    }
+
+    #[test]
+    fn test_rotate_jwt_key_rejects_used_kid() {
+        let mut keyring = keyring();
+        keyring.key_id = Some("first".to_string());
+
+        assert!(rotate(&mut keyring, "first", Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_keyring_file_replaces_config_keys() {
+        let path = std::env::temp_dir().join(format!("keyring-{}.yaml", uuid::Uuid::new_v4()));
+        let mut config = AppConfig {
+            jwt_keyring: Some(path.clone()),
+            ..app_config()
+        };
+        assert_eq!(config.keyring().unwrap().secret, "first");
+
+        let mut keyring = config.keyring().unwrap();
+        rotate(&mut keyring, "second", Utc::now()).unwrap();
+        keyring.save(&path).unwrap();
+        config.jwt_secret = "ignored".to_string();
+
+        let loaded = config.keyring().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.key_id.as_deref(), Some("second"));
+        assert_eq!(loaded.retired_keys[0].secret, "first");
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// RFC 5322 message, as saved by `FileMailer`.
+    fn to_message(&self) -> String {
+        format!(
+            "Date: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            Utc::now().to_rfc2822(),
+            self.to,
+            self.subject,
+            self.body
+        )
+    }
+}
+
+/// Delivers emails to users, e.g. password reset links.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> anyhow::Result<()>;
+}
+
+/// How emails are delivered. Nothing is sent unless a transport is configured.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(tag = "transport", rename_all = "snake_case")]
+pub enum MailerConfig {
+    /// Refuses to send, password reset and verification tokens are never delivered
+    #[default]
+    Disabled,
+    /// Logs the emails, tokens included, for local development only
+    Log,
+    /// Saves each email as an `.eml` file in `directory`
+    File { directory: PathBuf },
+}
+
+impl MailerConfig {
+    pub fn mailer(&self) -> Arc<dyn Mailer> {
+        match self {
+            MailerConfig::Disabled => Arc::new(DisabledMailer),
+            MailerConfig::Log => {
+                warn!("Emails are logged, tokens in them can be read from the logs");
+                Arc::new(LogMailer)
+            }
+            MailerConfig::File { directory } => Arc::new(FileMailer {
+                directory: directory.clone(),
+            }),
+        }
+    }
+}
+
+/// Mailer of `MailerConfig::Disabled`.
+pub struct DisabledMailer;
+
+#[async_trait]
+impl Mailer for DisabledMailer {
+    async fn send(&self, _email: &Email) -> anyhow::Result<()> {
+        Err(anyhow!("No mailer is configured"))
+    }
+}
+
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> anyhow::Result<()> {
+        info!(
+            "Email to {}\nSubject: {}\n\n{}",
+            email.to, email.subject, email.body
+        );
+        Ok(())
+    }
+}
+
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|err| anyhow!("Can't create {:?}: {}", self.directory, err))?;
+        let path = self.directory.join(format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            Uuid::new_v4().simple()
+        ));
+        tokio::fs::write(&path, email.to_message())
+            .await
+            .map_err(|err| anyhow!("Can't write {:?}: {}", path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_mailer() {
+        let directory = std::env::temp_dir().join(format!("bartender-mail-{}", Uuid::new_v4()));
+        let config = MailerConfig::File {
+            directory: directory.clone(),
+        };
+
+        config
+            .mailer()
+            .send(&Email {
+                to: "alice@example.com".to_string(),
+                subject: "Reset your password".to_string(),
+                body: "Token: abc".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&directory).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let message = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(message.contains("To: alice@example.com\r\n"));
+        assert!(message.contains("Subject: Reset your password\r\n\r\nToken: abc"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_default_mailer_sends_nothing() {
+        let email = Email {
+            to: "alice@example.com".to_string(),
+            subject: "Reset your password".to_string(),
+            body: "Token: abc".to_string(),
+        };
+        assert!(MailerConfig::default().mailer().send(&email).await.is_err());
+    }
+
+    #[test]
+    fn test_mailer_config() {
+        let config: MailerConfig =
+            serde_yaml::from_str("transport: file\ndirectory: mail").unwrap();
+        assert!(
+            matches!(config, MailerConfig::File { directory } if directory.to_str() == Some("mail"))
+        );
+
+        let config: MailerConfig = serde_yaml::from_str("transport: log").unwrap();
+        assert!(matches!(config, MailerConfig::Log));
+        assert!(matches!(MailerConfig::default(), MailerConfig::Disabled));
+    }
+}
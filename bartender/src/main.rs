@@ -1,19 +1,40 @@
 use crate::app::AppState;
-use crate::cli::Cli;
+use crate::cli::{ClientArgs, Cli, Command, CreateClientArgs, RoleArgs, RotateKeyArgs, UserArgs};
 use auth::tokens::TokenManager;
 use auth::JWTState;
+use anyhow::anyhow;
+use chrono::Utc;
 use log::{info, warn};
 use multitool_hg::database::postgres::new_postgres_pool;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use multitool_hg::logger::tracer_logger::new_tracer_logger;
+use repository::auth::AuthRepository;
+use repository::email_verifications::EmailVerificationRepository;
+use repository::login_attempts::LoginAttemptRepository;
+use repository::mfa_challenges::MfaChallengeRepository;
+use repository::password_resets::PasswordResetRepository;
+use repository::personal_tokens::PersonalTokenRepository;
+use repository::refresh_tokens::RefreshTokenRepository;
+use repository::service_clients::ServiceClientRepository;
+use repository::sessions::SessionRepository;
+use repository::revocation::RevocationRepository;
+use sqlx::PgPool;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::process;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal;
+use uuid::Uuid;
 
 mod api;
 mod app;
 mod cli;
 mod config;
+mod mailer;
+mod passwords;
+mod totp;
 
 #[tokio::main]
 async fn main() {
@@ -30,15 +51,70 @@ async fn run() -> anyhow::Result<()> {
     let config =
         config::BartenderConfig::new(Path::new(&cli.config)).expect("Failed to load config");
 
+    match cli.command {
+        Some(Command::RotateKey(args)) => rotate_key(config, args),
+        Some(Command::GrantRole(args)) => set_role(config, args, true).await,
+        Some(Command::RevokeRole(args)) => set_role(config, args, false).await,
+        Some(Command::DisableUser(args)) => set_active(config, args, false).await,
+        Some(Command::EnableUser(args)) => set_active(config, args, true).await,
+        Some(Command::CreateClient(args)) => create_client(config, args).await,
+        Some(Command::DeleteClient(args)) => delete_client(config, args).await,
+        None => serve(config).await,
+    }
+}
+
+async fn serve(config: config::BartenderConfig) -> anyhow::Result<()> {
     let database_pool = new_postgres_pool(config.database)
         .await
         .expect("Failed to create Postgres pool");
-    let token_manager = TokenManager::new(JWTState {
-        secret: config.app.jwt_secret,
+    let revocation_repository = Arc::new(RevocationRepository::new(Arc::new(
+        database_pool.clone(),
+    )));
+    let keyring = config.app.keyring()?;
+    let mut token_manager = TokenManager::new(JWTState {
+        key: keyring.jwt_key()?,
+        retired_keys: keyring.jwt_retired_keys()?,
+        issuer: config.app.jwt_issuer,
+        audience: config.app.jwt_audience,
+        leeway: config.app.jwt_leeway,
         access_token_expiration: config.app.access_token_expiration,
         refresh_token_expiration: config.app.refresh_token_expiration,
-    });
-    let app_state = Arc::new(AppState::new(database_pool, token_manager));
+    })
+    .with_revocation_store(
+        revocation_repository.clone(),
+        Duration::from_secs(config.app.revocation_cache_ttl),
+    );
+    token_manager = token_manager.with_personal_token_store(Arc::new(
+        PersonalTokenRepository::new(Arc::new(database_pool.clone())),
+    ));
+    if config.app.check_active_users {
+        token_manager = token_manager.with_user_lookup(
+            Arc::new(AuthRepository::new(Arc::new(database_pool.clone()))),
+            Duration::from_secs(config.app.active_user_cache_ttl),
+        );
+    }
+    tokio::spawn(delete_expired_tokens(
+        revocation_repository,
+        Arc::new(database_pool.clone()),
+        config
+            .app
+            .login_throttle
+            .account
+            .reset_after
+            .max(config.app.login_throttle.ip.reset_after),
+    ));
+    let app_state = Arc::new(
+        AppState::new(database_pool, token_manager, config.app.jwt_scopes)
+            .with_session_cookies(config.app.session_cookies)
+            .with_client_token_expiration(config.app.client_token_expiration)
+            .with_password_reset_expiration(config.app.password_reset_expiration)
+            .with_mailer(config.app.mailer.mailer())
+            .with_password_hasher(config.app.password_hashing.hasher()?)
+            .with_email_verification(config.app.email_verification)
+            .with_login_throttle(config.app.login_throttle)
+            .with_trusted_proxies(config.app.trusted_proxies)
+            .with_mfa(config.app.mfa),
+    );
 
     let app = api::create_router(app_state);
     let address = format!("{}:{}", config.app.host, config.app.port);
@@ -46,7 +122,11 @@ async fn run() -> anyhow::Result<()> {
         .await
         .expect("Failed to bind");
     let server = async {
-        axum::serve(listener, app)
+        // Peer addresses are recorded with sessions
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
             .await
             .expect("Failed to run server");
     };
@@ -72,3 +152,199 @@ async fn run() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Revocations, refresh tokens, sessions, password resets, email verifications, MFA
+/// challenges and login failures that have expired are useless, clean them up once an hour.
+/// Failures count for `login_attempts_reset_after` seconds.
+async fn delete_expired_tokens(
+    revocation_repository: Arc<RevocationRepository>,
+    database_pool: Arc<PgPool>,
+    login_attempts_reset_after: u64,
+) {
+    let refresh_token_repository = RefreshTokenRepository::new(database_pool.clone());
+    let session_repository = SessionRepository::new(database_pool.clone());
+    let password_reset_repository = PasswordResetRepository::new(database_pool.clone());
+    let email_verification_repository = EmailVerificationRepository::new(database_pool.clone());
+    let mfa_challenge_repository = MfaChallengeRepository::new(database_pool.clone());
+    let login_attempt_repository = LoginAttemptRepository::new(database_pool);
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        match revocation_repository.delete_expired().await {
+            Ok(deleted) => info!("Deleted {} expired token revocations", deleted),
+            Err(err) => warn!("Failed to delete expired token revocations: {}", err),
+        }
+        match refresh_token_repository.delete_expired().await {
+            Ok(deleted) => info!("Deleted {} expired refresh tokens", deleted),
+            Err(err) => warn!("Failed to delete expired refresh tokens: {}", err),
+        }
+        match session_repository.delete_ended().await {
+            Ok(deleted) => info!("Deleted {} ended sessions", deleted),
+            Err(err) => warn!("Failed to delete ended sessions: {}", err),
+        }
+        match password_reset_repository.delete_expired().await {
+            Ok(deleted) => info!("Deleted {} expired password resets", deleted),
+            Err(err) => warn!("Failed to delete expired password resets: {}", err),
+        }
+        match email_verification_repository.delete_expired().await {
+            Ok(deleted) => info!("Deleted {} expired email verifications", deleted),
+            Err(err) => warn!("Failed to delete expired email verifications: {}", err),
+        }
+        match mfa_challenge_repository.delete_expired().await {
+            Ok(deleted) => info!("Deleted {} expired MFA challenges", deleted),
+            Err(err) => warn!("Failed to delete expired MFA challenges: {}", err),
+        }
+        match login_attempt_repository
+            .delete_expired(login_attempts_reset_after as i64)
+            .await
+        {
+            Ok(deleted) => info!("Deleted {} expired login attempts", deleted),
+            Err(err) => warn!("Failed to delete expired login attempts: {}", err),
+        }
+    }
+}
+
+fn rotate_key(config: config::BartenderConfig, args: RotateKeyArgs) -> anyhow::Result<()> {
+    let keyring_path = config
+        .app
+        .jwt_keyring
+        .clone()
+        .ok_or_else(|| anyhow!("Set `jwt_keyring` in the config to rotate keys"))?;
+    let mut keyring = config.app.keyring()?;
+    let kid = args.kid.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let algorithm = args.algorithm.unwrap_or(keyring.algorithm);
+    let secret = match args.secret_file {
+        Some(path) => read_secret(&path)?,
+        None => String::new(),
+    };
+
+    keyring.rotate(
+        kid.clone(),
+        algorithm,
+        secret,
+        args.private_key,
+        Utc::now(),
+        config.app.refresh_token_expiration,
+    )?;
+    keyring.save(&keyring_path)?;
+
+    info!(
+        "Signing key {} is active. Restart bartender to apply it",
+        kid
+    );
+    Ok(())
+}
+
+/// Reads a secret from a file, or from stdin when the path is `-`.
+fn read_secret(path: &Path) -> anyhow::Result<String> {
+    let secret = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(path).map_err(|err| anyhow!("Can't read {:?}: {}", path, err))?
+    };
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+async fn set_role(
+    config: config::BartenderConfig,
+    args: RoleArgs,
+    grant: bool,
+) -> anyhow::Result<()> {
+    let database_pool = new_postgres_pool(config.database)
+        .await
+        .expect("Failed to create Postgres pool");
+    let auth_repository = AuthRepository::new(Arc::new(database_pool));
+
+    let result = if grant {
+        auth_repository.add_role(&args.username, &args.role).await
+    } else {
+        auth_repository
+            .remove_role(&args.username, &args.role)
+            .await
+    };
+    result.map_err(|err| anyhow::anyhow!("Can't update roles of {}: {:?}", args.username, err))?;
+
+    info!(
+        "Roles of {} updated. New tokens carry the change",
+        args.username
+    );
+    Ok(())
+}
+
+async fn set_active(
+    config: config::BartenderConfig,
+    args: UserArgs,
+    active: bool,
+) -> anyhow::Result<()> {
+    let database_pool = new_postgres_pool(config.database)
+        .await
+        .expect("Failed to create Postgres pool");
+    let auth_repository = AuthRepository::new(Arc::new(database_pool));
+
+    auth_repository
+        .set_active(&args.username, active)
+        .await
+        .map_err(|err| anyhow::anyhow!("Can't update {}: {:?}", args.username, err))?;
+
+    if active {
+        info!("User {} is enabled", args.username);
+    } else {
+        info!("User {} is disabled", args.username);
+    }
+    Ok(())
+}
+
+async fn create_client(
+    config: config::BartenderConfig,
+    args: CreateClientArgs,
+) -> anyhow::Result<()> {
+    if let Some(scope) = args
+        .scopes
+        .iter()
+        .find(|scope| !config.app.jwt_scopes.contains(scope))
+    {
+        return Err(anyhow::anyhow!("Unknown scope {}, see jwt_scopes", scope));
+    }
+
+    let database_pool = new_postgres_pool(config.database)
+        .await
+        .expect("Failed to create Postgres pool");
+    let service_client_repository = ServiceClientRepository::new(Arc::new(database_pool));
+
+    let client_id = Uuid::new_v4().simple().to_string();
+    let client_secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect();
+    // Hashed like passwords, only the hash is stored
+    let secret_hash = config.app.password_hashing.hasher()?.hash(&client_secret)?;
+
+    service_client_repository
+        .create(&client_id, &args.name, &secret_hash, &args.scopes)
+        .await
+        .map_err(|err| anyhow::anyhow!("Can't create client {}: {}", args.name, err))?;
+
+    // Секрет не хранится, показываем его один раз
+    println!("client_id: {}", client_id);
+    println!("client_secret: {}", client_secret);
+    Ok(())
+}
+
+async fn delete_client(config: config::BartenderConfig, args: ClientArgs) -> anyhow::Result<()> {
+    let database_pool = new_postgres_pool(config.database)
+        .await
+        .expect("Failed to create Postgres pool");
+    let service_client_repository = ServiceClientRepository::new(Arc::new(database_pool));
+
+    let deleted = service_client_repository
+        .delete(&args.client_id)
+        .await
+        .map_err(|err| anyhow::anyhow!("Can't delete client {}: {}", args.client_id, err))?;
+    if !deleted {
+        return Err(anyhow::anyhow!("Client {} not found", args.client_id));
+    }
+
+    info!("Client {} deleted", args.client_id);
+    Ok(())
+}
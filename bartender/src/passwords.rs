@@ -0,0 +1,217 @@
+//! Password hashing. New hashes use Argon2id, bcrypt hashes of earlier versions still verify
+//! and are replaced on the next login.
+
+use anyhow::anyhow;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHasher as _, PasswordVerifier, Version};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Hashes and verifies user passwords.
+pub trait PasswordHasher: Send + Sync {
+    /// PHC string of the password, with a new salt.
+    fn hash(&self, password: &str) -> anyhow::Result<String>;
+
+    /// Whether `password` matches `hash`. Hashes that can't be read never match.
+    fn verify(&self, password: &str, hash: &str) -> bool;
+
+    /// Whether `hash` is of another algorithm or parameters, so that it should be replaced
+    /// with a new hash once the password is known.
+    fn needs_rehash(&self, hash: &str) -> bool;
+}
+
+/// Argon2id parameters, see RFC 9106. The defaults are those recommended by OWASP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PasswordHashingConfig {
+    /// Memory in KiB
+    #[serde(default = "default_memory_cost")]
+    pub memory_cost: u32,
+    /// Number of passes over the memory
+    #[serde(default = "default_time_cost")]
+    pub time_cost: u32,
+    /// Number of lanes
+    #[serde(default = "default_parallelism")]
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashingConfig {
+    fn default() -> Self {
+        Self {
+            memory_cost: default_memory_cost(),
+            time_cost: default_time_cost(),
+            parallelism: default_parallelism(),
+        }
+    }
+}
+
+fn default_memory_cost() -> u32 {
+    Params::DEFAULT_M_COST
+}
+
+fn default_time_cost() -> u32 {
+    Params::DEFAULT_T_COST
+}
+
+fn default_parallelism() -> u32 {
+    Params::DEFAULT_P_COST
+}
+
+impl PasswordHashingConfig {
+    pub fn hasher(&self) -> anyhow::Result<Arc<dyn PasswordHasher>> {
+        Ok(Arc::new(Argon2idHasher::new(self)?))
+    }
+}
+
+/// bcrypt hashes are in the modular crypt format, with a `$2a$`, `$2b$`, `$2x$` or `$2y$`
+/// prefix instead of the `$argon2id$` of PHC strings.
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
+
+pub struct Argon2idHasher {
+    params: Params,
+}
+
+impl Argon2idHasher {
+    pub fn new(config: &PasswordHashingConfig) -> anyhow::Result<Self> {
+        let params = Params::new(
+            config.memory_cost,
+            config.time_cost,
+            config.parallelism,
+            None,
+        )
+        .map_err(|err| anyhow!("Invalid password_hashing parameters: {}", err))?;
+        Ok(Self { params })
+    }
+
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl Default for Argon2idHasher {
+    fn default() -> Self {
+        Self {
+            params: Params::DEFAULT,
+        }
+    }
+}
+
+impl PasswordHasher for Argon2idHasher {
+    fn hash(&self, password: &str) -> anyhow::Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self
+            .argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|err| anyhow!("Failed to hash password: {}", err))?;
+        Ok(hash.to_string())
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        if is_bcrypt(hash) {
+            return bcrypt::verify(password, hash).unwrap_or(false);
+        }
+        // Verified with the algorithm and parameters of the hash, not the current ones
+        match PasswordHash::new(hash) {
+            Ok(hash) => self
+                .argon2()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        if is_bcrypt(hash) {
+            return true;
+        }
+        let Ok(hash) = PasswordHash::new(hash) else {
+            return true;
+        };
+        if hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+        match Params::try_from(&hash) {
+            Ok(params) => {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, the defaults take a while in debug builds.
+    fn config() -> PasswordHashingConfig {
+        PasswordHashingConfig {
+            memory_cost: 64,
+            time_cost: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hasher = Argon2idHasher::new(&config()).unwrap();
+        let hash = hasher.hash("Password123!").unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+        assert!(hasher.verify("Password123!", &hash));
+        assert!(!hasher.verify("Password123?", &hash));
+        assert!(!hasher.needs_rehash(&hash));
+        assert_ne!(hash, hasher.hash("Password123!").unwrap());
+    }
+
+    #[test]
+    fn test_legacy_bcrypt_hash_verifies_and_needs_rehash() {
+        let hasher = Argon2idHasher::new(&config()).unwrap();
+        let hash = bcrypt::hash("Password123!", 4).unwrap();
+
+        assert!(hasher.verify("Password123!", &hash));
+        assert!(!hasher.verify("Password123?", &hash));
+        assert!(hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn test_hash_with_other_parameters_needs_rehash() {
+        let hash = Argon2idHasher::new(&config())
+            .unwrap()
+            .hash("Password123!")
+            .unwrap();
+        let hasher = Argon2idHasher::new(&PasswordHashingConfig {
+            time_cost: 2,
+            ..config()
+        })
+        .unwrap();
+
+        assert!(hasher.verify("Password123!", &hash));
+        assert!(hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn test_unreadable_hash_does_not_verify() {
+        let hasher = Argon2idHasher::new(&config()).unwrap();
+        assert!(!hasher.verify("Password123!", ""));
+        assert!(!hasher.verify("Password123!", "plain"));
+    }
+
+    #[test]
+    fn test_invalid_parameters_are_rejected() {
+        let config = PasswordHashingConfig {
+            memory_cost: 1,
+            ..config()
+        };
+        assert!(config.hasher().is_err());
+        assert!(PasswordHashingConfig::default().hasher().is_ok());
+    }
+}
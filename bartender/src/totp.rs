@@ -0,0 +1,101 @@
+//! RFC 6238 time-based one-time passwords, the second factor of a login.
+
+use anyhow::anyhow;
+use rand::RngCore;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const DIGITS: usize = 6;
+/// Seconds a code is valid for
+const STEP: u64 = 30;
+/// Codes of the previous and the next step are accepted too, for clocks that drift
+const SKEW: u64 = 1;
+
+/// New 160-bit secret, base32 encoded like authenticator apps expect it.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    Secret::Raw(secret.to_vec()).to_encoded().to_string()
+}
+
+fn totp(secret: &str, issuer: &str, account: &str) -> anyhow::Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|err| anyhow!("Invalid TOTP secret: {:?}", err))?;
+    // Unchecked, as the issuer and account of the URI may contain ':', they are encoded
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP,
+        secret,
+        Some(issuer.to_string()),
+        account.to_string(),
+    ))
+}
+
+/// `otpauth://` URI that adds the secret to an authenticator app, usually shown as a QR code.
+pub fn otpauth_uri(secret: &str, issuer: &str, account: &str) -> anyhow::Result<String> {
+    Ok(totp(secret, issuer, account)?.get_url())
+}
+
+/// Time step of `code` when it is valid at `now`, a Unix timestamp. The caller remembers
+/// the step so that a code can't be used twice.
+pub fn verify(secret: &str, code: &str, now: u64) -> Option<u64> {
+    let totp = totp(secret, "", "").ok()?;
+    let step = now / STEP;
+    (step.saturating_sub(SKEW)..=step + SKEW).find(|step| totp.check(code, step * STEP))
+}
+
+/// Code of the time step of `now`, as an authenticator app shows it.
+#[cfg(test)]
+pub fn generate(secret: &str, now: u64) -> String {
+    totp(secret, "", "").unwrap().generate(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret of the test vectors of RFC 6238, "12345678901234567890"
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_verify_rfc_6238_vectors() {
+        assert_eq!(verify(RFC_SECRET, "287082", 59), Some(1));
+        assert_eq!(verify(RFC_SECRET, "081804", 1111111109), Some(37037036));
+        assert_eq!(verify(RFC_SECRET, "050471", 1111111111), Some(37037037));
+    }
+
+    #[test]
+    fn test_verify_accepts_adjacent_steps_only() {
+        assert_eq!(verify(RFC_SECRET, "287082", 59 + STEP), Some(1));
+        assert_eq!(verify(RFC_SECRET, "287082", 59 + 2 * STEP), None);
+        assert_eq!(verify(RFC_SECRET, "000000", 59), None);
+        assert_eq!(verify("not base32!", "287082", 59), None);
+    }
+
+    #[test]
+    fn test_generated_secret_round_trips() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+
+        let totp = totp(&secret, "bartender", "alice").unwrap();
+        let code = totp.generate(1_000_000_000);
+        assert_eq!(
+            verify(&secret, &code, 1_000_000_000),
+            Some(1_000_000_000 / STEP)
+        );
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri(RFC_SECRET, "bartender", "alice@example.com").unwrap();
+        assert_eq!(
+            uri,
+            format!(
+                "otpauth://totp/bartender:alice%40example.com?secret={}&issuer=bartender",
+                RFC_SECRET
+            )
+        );
+    }
+}
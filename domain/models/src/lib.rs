@@ -1 +1,8 @@
 pub mod user;
+pub mod mfa_challenge;
+pub mod personal_token;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod service_client;
+pub mod session;
+pub mod totp_secret;
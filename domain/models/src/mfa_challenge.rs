@@ -0,0 +1,11 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Login waiting for its second factor. The token identifying it is only stored hashed.
+pub struct MfaChallengeModel {
+    pub user_id: Uuid,
+    /// Scopes the tokens get once the challenge is passed
+    pub scopes: Vec<String>,
+    pub failures: i32,
+    pub expires_at: DateTime<Utc>,
+}
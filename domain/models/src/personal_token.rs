@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Personal access token without its value, which is only stored hashed.
+pub struct PersonalTokenModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,7 @@
+use uuid::Uuid;
+
+/// Unused recovery code of a user, stored as a salted password hash.
+pub struct RecoveryCodeModel {
+    pub id: Uuid,
+    pub code_hash: String,
+}
@@ -0,0 +1,10 @@
+use chrono::{DateTime, Utc};
+
+/// Service authenticating with the client-credentials grant.
+pub struct ServiceClientModel {
+    pub client_id: String,
+    pub name: String,
+    pub secret_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,12 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Login on a device. Its refresh tokens form the family with the session id.
+pub struct SessionModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+}
@@ -0,0 +1,12 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// TOTP secret of a user, base32 encoded. Only confirmed secrets are asked for on login.
+pub struct TotpSecretModel {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    /// Time step of the last accepted code, so that a code can't be used twice
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,10 +1,14 @@
 use uuid::Uuid;
 
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ADMIN: &str = "admin";
+
 pub struct User {
     pub id: Uuid,
     pub username: String,
     pub email: String,
     pub password_hash: String,
+    pub roles: Vec<String>,
 }
 
 pub struct UserModel {
@@ -12,6 +16,7 @@ pub struct UserModel {
     pub username: String,
     pub email: String,
     pub password_hash: String,
+    pub roles: Vec<String>,
 }
 
 impl From<User> for UserModel {
@@ -21,6 +26,7 @@ impl From<User> for UserModel {
             username: user.username,
             email: user.email,
             password_hash: user.password_hash,
+            roles: user.roles,
         }
     }
 }
@@ -32,6 +38,7 @@ impl From<UserModel> for User {
             username: model.username,
             email: model.email,
             password_hash: model.password_hash,
+            roles: model.roles,
         }
     }
 }
@@ -1,5 +1,8 @@
+use async_trait::async_trait;
+use auth::users::{UserLookup, UserLookupError};
 use models::user::UserModel;
 use log::error;
+use sqlx::postgres::PgQueryResult;
 use sqlx::PgPool;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -35,6 +38,19 @@ fn handle_fetch_optional<T>(
     }
 }
 
+fn handle_rows_affected(
+    result: Result<PgQueryResult, sqlx::Error>
+) -> Result<(), AuthRepositoryError> {
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err(AuthRepositoryError::UserNotFound),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Database error: {}", e);
+            Err(AuthRepositoryError::DatabaseError(e))
+        }
+    }
+}
+
 impl AuthRepository {
     pub fn new(pool: Arc<PgPool>) -> Self {
         AuthRepository { pool }
@@ -42,11 +58,12 @@ impl AuthRepository {
 
     pub async fn create(&self, model: &UserModel) -> Result<(), AuthRepositoryError> {
         let query = sqlx::query!(
-            "INSERT INTO users (id, username, email, password_hash) VALUES ($1, $2, $3, $4)",
+            "INSERT INTO users (id, username, email, password_hash, roles) VALUES ($1, $2, $3, $4, $5)",
             model.id,
             model.username,
             model.email,
             model.password_hash,
+            &model.roles,
         );
 
         match query.execute(&*self.pool).await {
@@ -71,20 +88,114 @@ impl AuthRepository {
     ) -> Result<UserModel, AuthRepositoryError> {
         let query = sqlx::query_as!(
             UserModel,
-            "SELECT id, username, email, password_hash FROM users WHERE username = $1",
+            "SELECT id, username, email, password_hash, roles FROM users WHERE username = $1::citext",
             username
         );
         let result = query.fetch_optional(&*self.pool).await;
         handle_fetch_optional(result)
     }
 
+    pub async fn find_by_email(&self, email: &str) -> Result<UserModel, AuthRepositoryError> {
+        let query = sqlx::query_as!(
+            UserModel,
+            "SELECT id, username, email, password_hash, roles FROM users WHERE email = $1::citext",
+            email
+        );
+        let result = query.fetch_optional(&*self.pool).await;
+        handle_fetch_optional(result)
+    }
+
+    /// Finds the user by username or, when `login` contains '@', by email first and then by
+    /// username, as usernames registered before they were validated may contain '@'. Both
+    /// are compared case-insensitively.
+    pub async fn find_by_login(&self, login: &str) -> Result<UserModel, AuthRepositoryError> {
+        if login.contains('@') {
+            match self.find_by_email(login).await {
+                Err(AuthRepositoryError::UserNotFound) => {}
+                result => return result,
+            }
+        }
+        self.find_by_username(login).await
+    }
+
     pub async fn find_by_id(&self, id: Uuid) -> Result<UserModel, AuthRepositoryError> {
         let query = sqlx::query_as!(
             UserModel,
-            "SELECT id, username, email, password_hash FROM users WHERE id = $1",
+            "SELECT id, username, email, password_hash, roles FROM users WHERE id = $1",
             id
         );
         let result = query.fetch_optional(&*self.pool).await;
         handle_fetch_optional(result)
     }
+
+    pub async fn add_role(&self, username: &str, role: &str) -> Result<(), AuthRepositoryError> {
+        let query = sqlx::query!(
+            "UPDATE users SET roles = array_append(array_remove(roles, $2), $2), updated_at = NOW() WHERE username = $1::citext",
+            username,
+            role
+        );
+        handle_rows_affected(query.execute(&*self.pool).await)
+    }
+
+    pub async fn remove_role(&self, username: &str, role: &str) -> Result<(), AuthRepositoryError> {
+        let query = sqlx::query!(
+            "UPDATE users SET roles = array_remove(roles, $2), updated_at = NOW() WHERE username = $1::citext",
+            username,
+            role
+        );
+        handle_rows_affected(query.execute(&*self.pool).await)
+    }
+
+    pub async fn set_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+    ) -> Result<(), AuthRepositoryError> {
+        let query = sqlx::query!(
+            "UPDATE users SET password_hash = $2, updated_at = NOW() WHERE id = $1",
+            id,
+            password_hash
+        );
+        handle_rows_affected(query.execute(&*self.pool).await)
+    }
+
+    /// Whether the user can log in and use its tokens.
+    pub async fn is_active(&self, id: Uuid) -> Result<bool, AuthRepositoryError> {
+        let query = sqlx::query_scalar!("SELECT is_active FROM users WHERE id = $1", id);
+        let result = query.fetch_optional(&*self.pool).await;
+        handle_fetch_optional(result)
+    }
+
+    pub async fn is_email_verified(&self, id: Uuid) -> Result<bool, AuthRepositoryError> {
+        let query = sqlx::query_scalar!(
+            r#"SELECT email_verified_at IS NOT NULL AS "verified!" FROM users WHERE id = $1"#,
+            id
+        );
+        let result = query.fetch_optional(&*self.pool).await;
+        handle_fetch_optional(result)
+    }
+
+    pub async fn set_active(&self, username: &str, active: bool) -> Result<(), AuthRepositoryError> {
+        let query = sqlx::query!(
+            "UPDATE users SET is_active = $2, updated_at = NOW() WHERE username = $1::citext",
+            username,
+            active
+        );
+        handle_rows_affected(query.execute(&*self.pool).await)
+    }
+}
+
+#[async_trait]
+impl UserLookup for AuthRepository {
+    async fn is_active(&self, user_id: &str) -> Result<bool, UserLookupError> {
+        let Ok(id) = Uuid::parse_str(user_id) else {
+            return Ok(false);
+        };
+
+        match AuthRepository::is_active(self, id).await {
+            Ok(active) => Ok(active),
+            Err(AuthRepositoryError::DatabaseError(e)) => Err(e.into()),
+            Err(_) => Ok(false),
+        }
+    }
 }
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Email verification tokens of the `email_verifications` table, stored by hash.
+pub struct EmailVerificationRepository {
+    pool: Arc<PgPool>,
+}
+
+impl EmailVerificationRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        EmailVerificationRepository { pool }
+    }
+
+    /// Stores a new verification token of the user, replacing the ones sent before.
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM email_verifications WHERE user_id = $1", user_id)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO email_verifications (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+            user_id,
+            token_hash,
+            expires_at
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await
+    }
+
+    /// Uses up the token and marks the email of its user as verified. `None` when the token
+    /// has expired or was never issued.
+    pub async fn verify(&self, token_hash: &str) -> Result<Option<Uuid>, sqlx::Error> {
+        let query = sqlx::query_scalar!(
+            "WITH verification AS (\
+                 DELETE FROM email_verifications WHERE token_hash = $1 AND expires_at > NOW() \
+                 RETURNING user_id\
+             ) \
+             UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW() \
+             FROM verification WHERE users.id = verification.user_id RETURNING users.id",
+            token_hash
+        );
+        query.fetch_optional(&*self.pool).await
+    }
+
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!("DELETE FROM email_verifications WHERE expires_at <= NOW()");
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -1 +1,11 @@
 pub mod auth;
+pub mod email_verifications;
+pub mod login_attempts;
+pub mod mfa_challenges;
+pub mod revocation;
+pub mod password_resets;
+pub mod personal_tokens;
+pub mod refresh_tokens;
+pub mod service_clients;
+pub mod sessions;
+pub mod totp;
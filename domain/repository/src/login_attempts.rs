@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// Failed logins of the `login_attempts` table, counted per client IP and per account.
+pub struct LoginAttemptRepository {
+    pool: Arc<PgPool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptScope {
+    Ip,
+    Account,
+}
+
+impl AttemptScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            AttemptScope::Ip => "ip",
+            AttemptScope::Account => "account",
+        }
+    }
+}
+
+/// Outcome of `LoginAttemptRepository::reserve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reservation {
+    /// The attempt may go ahead. `locked_until` is the lockout it started
+    Reserved {
+        failures: i32,
+        locked_until: Option<DateTime<Utc>>,
+    },
+    /// Refused, nothing was counted
+    Locked { until: DateTime<Utc> },
+}
+
+impl LoginAttemptRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        LoginAttemptRepository { pool }
+    }
+
+    /// Counts an attempt of `key` as failed before it is checked, unless `key` is locked out.
+    /// `lockout` gets the failures so far and returns when the lockout they lead to ends.
+    /// The count starts over when the previous failure is older than `reset_after` seconds.
+    ///
+    /// The row stays locked until the lockout is stored, so concurrent attempts are counted
+    /// one after the other and none gets past a lockout.
+    pub async fn reserve(
+        &self,
+        scope: AttemptScope,
+        key: &str,
+        reset_after: i64,
+        lockout: impl FnOnce(i32) -> Option<DateTime<Utc>>,
+    ) -> Result<Reservation, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let failures = sqlx::query_scalar!(
+            "INSERT INTO login_attempts (scope, key, failures) VALUES ($1, $2, 1) \
+             ON CONFLICT (scope, key) DO UPDATE SET \
+             failures = CASE WHEN login_attempts.last_failed_at < NOW() - make_interval(secs => $3) \
+                 THEN 1 ELSE login_attempts.failures + 1 END, \
+             last_failed_at = NOW() \
+             WHERE login_attempts.locked_until IS NULL OR login_attempts.locked_until <= NOW() \
+             RETURNING failures",
+            scope.as_str(),
+            key,
+            reset_after as f64
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(failures) = failures else {
+            let until = sqlx::query_scalar!(
+                r#"SELECT locked_until AS "locked_until!" FROM login_attempts
+                   WHERE scope = $1 AND key = $2"#,
+                scope.as_str(),
+                key
+            )
+            .fetch_one(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            return Ok(Reservation::Locked { until });
+        };
+
+        let locked_until = lockout(failures);
+        if let Some(until) = locked_until {
+            sqlx::query!(
+                "UPDATE login_attempts SET locked_until = $3 WHERE scope = $1 AND key = $2",
+                scope.as_str(),
+                key,
+                until
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(Reservation::Reserved {
+            failures,
+            locked_until,
+        })
+    }
+
+    /// Takes back an attempt counted by `reserve` that turned out to succeed, with the
+    /// lockout it started, if any.
+    pub async fn release(
+        &self,
+        scope: AttemptScope,
+        key: &str,
+        locked_until: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE login_attempts SET failures = GREATEST(failures - 1, 0), \
+             locked_until = CASE WHEN locked_until = $3 THEN NULL ELSE locked_until END \
+             WHERE scope = $1 AND key = $2",
+            scope.as_str(),
+            key,
+            locked_until
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    /// Forgets the failures of `key`, after a successful login.
+    pub async fn clear(&self, scope: AttemptScope, key: &str) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "DELETE FROM login_attempts WHERE scope = $1 AND key = $2",
+            scope.as_str(),
+            key
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    /// Failures older than `reset_after` seconds no longer count, unless still locked out.
+    pub async fn delete_expired(&self, reset_after: i64) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!(
+            "DELETE FROM login_attempts \
+             WHERE last_failed_at < NOW() - make_interval(secs => $1) \
+             AND (locked_until IS NULL OR locked_until <= NOW())",
+            reset_after as f64
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use models::mfa_challenge::MfaChallengeModel;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Logins waiting for their second factor, of the `mfa_challenges` table. Stored by hash of
+/// the challenge token.
+pub struct MfaChallengeRepository {
+    pool: Arc<PgPool>,
+}
+
+impl MfaChallengeRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        MfaChallengeRepository { pool }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        scopes: &[String],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "INSERT INTO mfa_challenges (user_id, token_hash, scopes, expires_at) \
+             VALUES ($1, $2, $3, $4)",
+            user_id,
+            token_hash,
+            scopes,
+            expires_at
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    /// Challenge that can still be passed. `None` when it expired, was passed or failed
+    /// `max_failures` times.
+    pub async fn find(
+        &self,
+        token_hash: &str,
+        max_failures: i32,
+    ) -> Result<Option<MfaChallengeModel>, sqlx::Error> {
+        let query = sqlx::query_as!(
+            MfaChallengeModel,
+            "SELECT user_id, scopes, failures, expires_at FROM mfa_challenges \
+             WHERE token_hash = $1 AND expires_at > NOW() AND failures < $2",
+            token_hash,
+            max_failures
+        );
+        query.fetch_optional(&*self.pool).await
+    }
+
+    pub async fn record_failure(&self, token_hash: &str) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE mfa_challenges SET failures = failures + 1 WHERE token_hash = $1",
+            token_hash
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    /// Passes the challenge. Only one of concurrent requests with the same token gets `true`.
+    pub async fn consume(&self, token_hash: &str) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!(
+            "DELETE FROM mfa_challenges WHERE token_hash = $1 AND expires_at > NOW()",
+            token_hash
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!("DELETE FROM mfa_challenges WHERE expires_at <= NOW()");
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Password reset tokens of the `password_resets` table, stored by hash.
+pub struct PasswordResetRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PasswordResetRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        PasswordResetRepository { pool }
+    }
+
+    /// Stores a new reset token of the user. Tokens requested before stop working, only the
+    /// latest email is valid.
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM password_resets WHERE user_id = $1 AND used_at IS NULL",
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO password_resets (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+            user_id,
+            token_hash,
+            expires_at
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await
+    }
+
+    /// User of the token, `None` when the token was used before, has expired or was never
+    /// issued.
+    pub async fn find_valid(&self, token_hash: &str) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "SELECT user_id FROM password_resets \
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()",
+            token_hash
+        )
+        .fetch_optional(&*self.pool)
+        .await
+    }
+
+    /// Marks the token as used and sets the password of its user, in one transaction. Returns
+    /// the user, `None` when the token was used before, has expired or was never issued.
+    pub async fn reset_password(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let user_id = sqlx::query_scalar!(
+            "UPDATE password_resets SET used_at = NOW() \
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW() RETURNING user_id",
+            token_hash
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+        sqlx::query!(
+            "UPDATE users SET password_hash = $2, updated_at = NOW() WHERE id = $1",
+            user_id,
+            password_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Some(user_id))
+    }
+
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!("DELETE FROM password_resets WHERE expires_at <= NOW()");
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
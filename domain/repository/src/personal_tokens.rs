@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use auth::personal_tokens::{PersonalToken, PersonalTokenError, PersonalTokenStore};
+use chrono::{DateTime, Duration, Utc};
+use log::error;
+use models::personal_token::PersonalTokenModel;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Seconds `last_used_at` may lag behind, so that a token used on every request isn't
+/// written on every request.
+const LAST_USED_PRECISION: i64 = 60;
+
+/// Personal access tokens of the `personal_access_tokens` table, stored by hash.
+pub struct PersonalTokenRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PersonalTokenRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        PersonalTokenRepository { pool }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        token_hash: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<PersonalTokenModel, sqlx::Error> {
+        let query = sqlx::query_as!(
+            PersonalTokenModel,
+            "INSERT INTO personal_access_tokens (user_id, name, token_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, $5) \
+             RETURNING id, user_id, name, scopes, expires_at, last_used_at, created_at",
+            user_id,
+            name,
+            token_hash,
+            scopes,
+            expires_at
+        );
+        query.fetch_one(&*self.pool).await
+    }
+
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<PersonalTokenModel>, sqlx::Error> {
+        let query = sqlx::query_as!(
+            PersonalTokenModel,
+            "SELECT id, user_id, name, scopes, expires_at, last_used_at, created_at FROM personal_access_tokens \
+             WHERE user_id = $1 ORDER BY created_at",
+            user_id
+        );
+        query.fetch_all(&*self.pool).await
+    }
+
+    /// Deletes a token of the user. `false` when the user has no such token.
+    pub async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!(
+            "DELETE FROM personal_access_tokens WHERE id = $1 AND user_id = $2",
+            id,
+            user_id
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl PersonalTokenStore for PersonalTokenRepository {
+    /// Also records when the token was last used, at most once per `LAST_USED_PRECISION`.
+    async fn find(&self, token_hash: &str) -> Result<Option<PersonalToken>, PersonalTokenError> {
+        let query = sqlx::query!(
+            "SELECT p.id, p.user_id, u.roles, p.scopes, p.expires_at, p.last_used_at \
+             FROM personal_access_tokens p JOIN users u ON u.id = p.user_id \
+             WHERE p.token_hash = $1 AND (p.expires_at IS NULL OR p.expires_at > NOW())",
+            token_hash
+        );
+        let record = match query.fetch_optional(&*self.pool).await {
+            Ok(Some(record)) => record,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                error!("Database error: {}", e);
+                return Err(e.into());
+            }
+        };
+
+        let used_before = Utc::now() - Duration::seconds(LAST_USED_PRECISION);
+        if record
+            .last_used_at
+            .is_none_or(|last_used_at| last_used_at < used_before)
+        {
+            let query = sqlx::query!(
+                "UPDATE personal_access_tokens SET last_used_at = NOW() WHERE id = $1",
+                record.id
+            );
+            if let Err(e) = query.execute(&*self.pool).await {
+                error!("Database error: {}", e);
+                return Err(e.into());
+            }
+        }
+
+        Ok(Some(PersonalToken {
+            user_id: record.user_id.to_string(),
+            roles: record.roles,
+            scopes: record.scopes,
+            expires_at: record.expires_at,
+        }))
+    }
+}
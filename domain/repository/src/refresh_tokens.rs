@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use models::refresh_token::RefreshTokenModel;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Issued refresh tokens of the `refresh_tokens` table, stored by hash.
+pub struct RefreshTokenRepository {
+    pool: Arc<PgPool>,
+}
+
+/// Outcome of presenting a refresh token.
+pub enum RefreshTokenUse {
+    /// First use: the token is now spent and can be exchanged for a new one.
+    Rotated(RefreshTokenModel),
+    /// The token was spent before, so it was replayed by someone.
+    Reused(RefreshTokenModel),
+    /// Revoked, expired or never issued.
+    Invalid,
+}
+
+impl RefreshTokenRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        RefreshTokenRepository { pool }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4)",
+            user_id,
+            family_id,
+            token_hash,
+            expires_at
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    /// Marks the token as used. Only one of concurrent requests with the same token gets
+    /// `Rotated`, the others get `Reused`.
+    pub async fn consume(&self, token_hash: &str) -> Result<RefreshTokenUse, sqlx::Error> {
+        let query = sqlx::query_as!(
+            RefreshTokenModel,
+            "UPDATE refresh_tokens SET used_at = NOW() \
+             WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW() \
+             RETURNING id, user_id, family_id, expires_at, used_at, revoked_at, created_at",
+            token_hash
+        );
+        if let Some(token) = query.fetch_optional(&*self.pool).await? {
+            return Ok(RefreshTokenUse::Rotated(token));
+        }
+
+        let query = sqlx::query_as!(
+            RefreshTokenModel,
+            "SELECT id, user_id, family_id, expires_at, used_at, revoked_at, created_at \
+             FROM refresh_tokens WHERE token_hash = $1",
+            token_hash
+        );
+        Ok(match query.fetch_optional(&*self.pool).await? {
+            Some(token)
+                if token.used_at.is_some()
+                    && token.revoked_at.is_none()
+                    && token.expires_at > Utc::now() =>
+            {
+                RefreshTokenUse::Reused(token)
+            }
+            _ => RefreshTokenUse::Invalid,
+        })
+    }
+
+    /// Revokes every token of the family, the current one included.
+    pub async fn revoke_family(&self, family_id: Uuid) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND revoked_at IS NULL",
+            family_id
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Revokes the family of the token, ending the session it belongs to. Returns the family
+    /// id, `None` when no such token was issued.
+    pub async fn revoke_family_of(&self, token_hash: &str) -> Result<Option<Uuid>, sqlx::Error> {
+        let query = sqlx::query_scalar!(
+            "SELECT family_id FROM refresh_tokens WHERE token_hash = $1",
+            token_hash
+        );
+        let family_id = query.fetch_optional(&*self.pool).await?;
+        if let Some(family_id) = family_id {
+            self.revoke_family(family_id).await?;
+        }
+        Ok(family_id)
+    }
+
+    /// Revokes every refresh token of the user, ending all of its sessions. Returns the
+    /// families that were still active.
+    pub async fn revoke_all(&self, user_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+        let query = sqlx::query_scalar!(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL \
+             RETURNING family_id",
+            user_id
+        );
+        let mut family_ids = query.fetch_all(&*self.pool).await?;
+        family_ids.sort();
+        family_ids.dedup();
+        Ok(family_ids)
+    }
+
+    /// Revokes the refresh tokens of every session of the user but `family_id`. Returns the
+    /// families that were still active.
+    pub async fn revoke_all_except(
+        &self,
+        user_id: Uuid,
+        family_id: Uuid,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let query = sqlx::query_scalar!(
+            "UPDATE refresh_tokens SET revoked_at = NOW() \
+             WHERE user_id = $1 AND family_id <> $2 AND revoked_at IS NULL \
+             RETURNING family_id",
+            user_id,
+            family_id
+        );
+        let mut family_ids = query.fetch_all(&*self.pool).await?;
+        family_ids.sort();
+        family_ids.dedup();
+        Ok(family_ids)
+    }
+
+    /// Expired tokens fail validation anyway, reuse of them needs no detection.
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!("DELETE FROM refresh_tokens WHERE expires_at <= NOW()");
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use auth::revocation::{RevocationError, RevocationStore};
+use chrono::{DateTime, Utc};
+use log::error;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// `RevocationStore` backed by the `revoked_tokens` table, shared by all service instances.
+pub struct RevocationRepository {
+    pool: Arc<PgPool>,
+}
+
+impl RevocationRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        RevocationRepository { pool }
+    }
+
+    /// Drops revocations of tokens that have expired anyway.
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!("DELETE FROM revoked_tokens WHERE expires_at <= NOW()");
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl RevocationStore for RevocationRepository {
+    async fn revoke(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<(), RevocationError> {
+        let query = sqlx::query!(
+            "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT (jti) DO NOTHING",
+            jti,
+            expires_at
+        );
+
+        match query.execute(&*self.pool).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn is_revoked(&self, jti: &str) -> Result<bool, RevocationError> {
+        let query = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = $1) AS "revoked!""#,
+            jti
+        );
+
+        match query.fetch_one(&*self.pool).await {
+            Ok(revoked) => Ok(revoked),
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use models::service_client::ServiceClientModel;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// Registered service clients of the `service_clients` table.
+pub struct ServiceClientRepository {
+    pool: Arc<PgPool>,
+}
+
+impl ServiceClientRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        ServiceClientRepository { pool }
+    }
+
+    pub async fn create(
+        &self,
+        client_id: &str,
+        name: &str,
+        secret_hash: &str,
+        scopes: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "INSERT INTO service_clients (client_id, name, secret_hash, scopes) VALUES ($1, $2, $3, $4)",
+            client_id,
+            name,
+            secret_hash,
+            scopes
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    pub async fn find(&self, client_id: &str) -> Result<Option<ServiceClientModel>, sqlx::Error> {
+        let query = sqlx::query_as!(
+            ServiceClientModel,
+            "SELECT client_id, name, secret_hash, scopes, created_at FROM service_clients WHERE client_id = $1",
+            client_id
+        );
+        query.fetch_optional(&*self.pool).await
+    }
+
+    /// `false` when there is no such client.
+    pub async fn delete(&self, client_id: &str) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!("DELETE FROM service_clients WHERE client_id = $1", client_id);
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
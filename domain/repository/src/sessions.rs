@@ -0,0 +1,87 @@
+use models::session::SessionModel;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Sessions of the `sessions` table. A session lasts as long as its refresh token family.
+pub struct SessionRepository {
+    pool: Arc<PgPool>,
+}
+
+impl SessionRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        SessionRepository { pool }
+    }
+
+    /// Starts a session on login. Its id is the family id of the refresh tokens.
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<Uuid, sqlx::Error> {
+        let query = sqlx::query_scalar!(
+            "INSERT INTO sessions (user_id, user_agent, ip_address) VALUES ($1, $2, $3) RETURNING id",
+            user_id,
+            user_agent,
+            ip_address
+        );
+        query.fetch_one(&*self.pool).await
+    }
+
+    /// Records a refresh, from wherever the device is now.
+    pub async fn touch(
+        &self,
+        id: Uuid,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE sessions SET last_used_at = NOW(), user_agent = COALESCE($2, user_agent), \
+             ip_address = COALESCE($3, ip_address) WHERE id = $1",
+            id,
+            user_agent,
+            ip_address
+        );
+        query.execute(&*self.pool).await?;
+        Ok(())
+    }
+
+    /// Sessions that still have a usable refresh token, most recently used first.
+    pub async fn list_active(&self, user_id: Uuid) -> Result<Vec<SessionModel>, sqlx::Error> {
+        let query = sqlx::query_as!(
+            SessionModel,
+            "SELECT id, user_id, user_agent, ip_address, created_at, last_used_at FROM sessions s \
+             WHERE s.user_id = $1 AND EXISTS (\
+                 SELECT 1 FROM refresh_tokens t WHERE t.family_id = s.id \
+                 AND t.used_at IS NULL AND t.revoked_at IS NULL AND t.expires_at > NOW()\
+             ) ORDER BY last_used_at DESC",
+            user_id
+        );
+        query.fetch_all(&*self.pool).await
+    }
+
+    /// Revokes the refresh tokens of a session of the user. `false` when the user has no
+    /// such session or it has ended already.
+    pub async fn revoke(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE refresh_tokens SET revoked_at = NOW() \
+             WHERE family_id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            id,
+            user_id
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drops sessions whose refresh tokens have all expired and been deleted. Sessions just
+    /// created on login may have no refresh token yet, so they are left alone.
+    pub async fn delete_ended(&self) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query!(
+            "DELETE FROM sessions s WHERE s.last_used_at < NOW() - INTERVAL '1 hour' \
+             AND NOT EXISTS (SELECT 1 FROM refresh_tokens t WHERE t.family_id = s.id)"
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
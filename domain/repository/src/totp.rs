@@ -0,0 +1,137 @@
+use models::recovery_code::RecoveryCodeModel;
+use models::totp_secret::TotpSecretModel;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// TOTP secrets of the `totp_secrets` table and the recovery codes of the `recovery_codes`
+/// table, stored as salted password hashes. Recovery codes are issued when a secret is confirmed.
+pub struct TotpRepository {
+    pool: Arc<PgPool>,
+}
+
+impl TotpRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        TotpRepository { pool }
+    }
+
+    /// Stores a pending secret of the user, replacing a pending one. `false` when the user
+    /// has a confirmed secret already.
+    pub async fn enroll(&self, user_id: Uuid, secret: &str) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!(
+            "INSERT INTO totp_secrets (user_id, secret) VALUES ($1, $2) \
+             ON CONFLICT (user_id) DO UPDATE SET secret = $2, created_at = NOW() \
+             WHERE totp_secrets.confirmed_at IS NULL",
+            user_id,
+            secret
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find(&self, user_id: Uuid) -> Result<Option<TotpSecretModel>, sqlx::Error> {
+        let query = sqlx::query_as!(
+            TotpSecretModel,
+            "SELECT user_id, secret, confirmed_at, last_used_step, created_at \
+             FROM totp_secrets WHERE user_id = $1",
+            user_id
+        );
+        query.fetch_optional(&*self.pool).await
+    }
+
+    /// Whether login asks the user for a code.
+    pub async fn is_enabled(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                   SELECT 1 FROM totp_secrets WHERE user_id = $1 AND confirmed_at IS NOT NULL
+               ) AS "enabled!""#,
+            user_id
+        );
+        query.fetch_one(&*self.pool).await
+    }
+
+    /// Confirms the pending secret with the code of time step `step` and replaces the
+    /// recovery codes of the user. `false` when no secret is pending.
+    pub async fn confirm(
+        &self,
+        user_id: Uuid,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let result = sqlx::query!(
+            "UPDATE totp_secrets SET confirmed_at = NOW(), last_used_step = $2 \
+             WHERE user_id = $1 AND confirmed_at IS NULL",
+            user_id,
+            step
+        )
+        .execute(&mut *transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::TEXT[])",
+            user_id,
+            recovery_code_hashes
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    /// Records that the code of time step `step` was used. `false` when that step or a later
+    /// one was used before, i.e. the code is replayed.
+    pub async fn use_step(&self, user_id: Uuid, step: i64) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE totp_secrets SET last_used_step = $2 \
+             WHERE user_id = $1 AND confirmed_at IS NOT NULL \
+             AND (last_used_step IS NULL OR last_used_step < $2)",
+            user_id,
+            step
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Recovery codes of the user that can still be spent, to find the one entered among.
+    pub async fn unused_recovery_codes(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<RecoveryCodeModel>, sqlx::Error> {
+        let query = sqlx::query_as!(
+            RecoveryCodeModel,
+            "SELECT id, code_hash FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+            user_id
+        );
+        query.fetch_all(&*self.pool).await
+    }
+
+    /// Spends a recovery code. `false` when it was spent before, e.g. by a concurrent request.
+    pub async fn use_recovery_code(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query!(
+            "UPDATE recovery_codes SET used_at = NOW() WHERE id = $1 AND used_at IS NULL",
+            id
+        );
+        let result = query.execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Turns two-factor authentication off. `false` when the user had no secret.
+    pub async fn delete(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+            .execute(&mut *transaction)
+            .await?;
+        let result = sqlx::query!("DELETE FROM totp_secrets WHERE user_id = $1", user_id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Entries kept by the caches of this crate.
+pub(crate) const CACHE_CAPACITY: usize = 10_000;
+
+/// Values kept until they expire, for at most `capacity` keys. When it is full, expired
+/// entries are dropped first, then the ones expiring soonest.
+pub(crate) struct TtlCache<V> {
+    capacity: usize,
+    entries: Mutex<HashMap<String, (V, Instant)>>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Value of `key` unless it has expired.
+    pub(crate) fn get(&self, key: &str) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        let (value, expires_at) = entries.get(key)?;
+        (*expires_at > Instant::now()).then(|| value.clone())
+    }
+
+    pub(crate) fn insert(&self, key: &str, value: V, expires_at: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(key) {
+            let now = Instant::now();
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        if entries.len() >= self.capacity && !entries.contains_key(key) {
+            let soonest = entries
+                .iter()
+                .min_by_key(|(_, (_, expires_at))| *expires_at)
+                .map(|(key, _)| key.clone());
+            if let Some(soonest) = soonest {
+                entries.remove(&soonest);
+            }
+        }
+        entries.insert(key.to_string(), (value, expires_at));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn in_secs(secs: u64) -> Instant {
+        Instant::now() + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_get_until_expired() {
+        let cache = TtlCache::new(10);
+        cache.insert("fresh", 1, in_secs(60));
+        cache.insert("expired", 2, Instant::now());
+
+        assert_eq!(cache.get("fresh"), Some(1));
+        assert_eq!(cache.get("expired"), None);
+        assert_eq!(cache.get("unknown"), None);
+
+        cache.insert("fresh", 3, in_secs(60));
+        assert_eq!(cache.get("fresh"), Some(3));
+    }
+
+    #[test]
+    fn test_full_cache_drops_expired_then_soonest_expiring() {
+        let cache = TtlCache::new(3);
+        cache.insert("expired", 0, Instant::now());
+        cache.insert("soon", 1, in_secs(60));
+        cache.insert("later", 2, in_secs(120));
+
+        cache.insert("new", 3, in_secs(180));
+        assert_eq!(cache.entries.lock().unwrap().len(), 3);
+        assert_eq!(cache.get("soon"), Some(1));
+
+        cache.insert("newer", 4, in_secs(240));
+        assert_eq!(cache.entries.lock().unwrap().len(), 3);
+        assert_eq!(cache.get("soon"), None);
+        assert_eq!(cache.get("later"), Some(2));
+        assert_eq!(cache.get("newer"), Some(4));
+    }
+}
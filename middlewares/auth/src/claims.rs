@@ -1,22 +1,98 @@
 use chrono::{Duration, Utc};
 use serde::{Serialize, Deserialize};
 use models::user::User;
+use uuid::Uuid;
+
+/// What a token may be used for. Checked on every validation so that a refresh token
+/// can't be sent as a Bearer token and an access token can't be refreshed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenUse {
+    Access,
+    Refresh,
+}
+
+/// Who the `sub` is: a person or a registered service client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Principal {
+    #[default]
+    User,
+    Service,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    pub sub: String,            // User ID
+    pub sub: String,            // User ID or client_id
     pub exp: usize,             // Expiration timestamp
-    // pub iat: usize,          // Issued at timestamp
-    // pub roles: Vec<String>,  // Роли пользователя
-    // pub aud: String,         // Audience
-    // pub iss: String,         // Issuer
+    pub iat: usize,             // Issued at timestamp
+    pub nbf: usize,             // Not valid before timestamp
+    pub iss: String,            // Issuer
+    pub aud: Vec<String>,       // Audience
+    pub jti: String,            // Unique token ID
+    pub token_use: TokenUse,    // Access or refresh token
+    #[serde(default)]
+    pub roles: Vec<String>,     // Роли пользователя
+    #[serde(default)]
+    pub scope: String,          // Space-separated OAuth2 scopes
+    #[serde(default)]
+    pub principal: Principal,   // Пользователь или сервис
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,    // Login session of the user
 }
 
 impl Claims {
-    pub fn from_user(user: &User, expiration: Duration) -> Self {
+    pub fn from_user(
+        user: &User,
+        expiration: Duration,
+        token_use: TokenUse,
+        issuer: &str,
+        audience: &[String],
+        scopes: &[String],
+    ) -> Self {
+        let now = Utc::now();
         Self {
             sub: String::from(user.id),
-            exp: (Utc::now() + expiration).timestamp() as usize,
+            exp: (now + expiration).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            nbf: now.timestamp() as usize,
+            iss: issuer.to_string(),
+            aud: audience.to_vec(),
+            jti: Uuid::new_v4().to_string(),
+            token_use,
+            roles: user.roles.clone(),
+            scope: scopes.join(" "),
+            principal: Principal::User,
+            sid: None,
+        }
+    }
+
+    /// Access token of a service client obtained with the client-credentials grant.
+    pub fn from_service(
+        client_id: &str,
+        expiration: Duration,
+        issuer: &str,
+        audience: &[String],
+        scopes: &[String],
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            sub: client_id.to_string(),
+            exp: (now + expiration).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            nbf: now.timestamp() as usize,
+            iss: issuer.to_string(),
+            aud: audience.to_vec(),
+            jti: Uuid::new_v4().to_string(),
+            token_use: TokenUse::Access,
+            roles: vec![],
+            scope: scopes.join(" "),
+            principal: Principal::Service,
+            sid: None,
         }
     }
+
+    pub fn scopes(&self) -> Vec<String> {
+        self.scope.split_whitespace().map(String::from).collect()
+    }
 }
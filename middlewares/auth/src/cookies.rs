@@ -0,0 +1,130 @@
+use crate::rejection::AuthError;
+use axum::http::{HeaderMap, Method};
+use headers::{authorization::Bearer, Authorization, Cookie, HeaderMapExt};
+
+/// `HttpOnly` cookie with the access token.
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+/// `HttpOnly` cookie with the refresh token, sent to the auth endpoints only.
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+/// Cookie readable by JavaScript, echoed in `CSRF_HEADER` on state-changing requests.
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Token of the request: the `Authorization: Bearer` header, otherwise the access token
+/// cookie. `None` when the request carries neither.
+///
+/// Browsers attach cookies to cross-site requests too, so a cookie is only accepted on a
+/// state-changing method together with a matching CSRF header (double-submit).
+pub fn request_token(
+    headers: &HeaderMap,
+    method: &Method,
+) -> Result<Option<String>, AuthError> {
+    if let Some(Authorization(bearer)) = headers.typed_get::<Authorization<Bearer>>() {
+        return Ok(Some(bearer.token().to_string()));
+    }
+
+    let Some(token) = cookie(headers, ACCESS_TOKEN_COOKIE) else {
+        return Ok(None);
+    };
+    if !is_safe_method(method) {
+        verify_csrf(headers)?;
+    }
+    Ok(Some(token))
+}
+
+/// Value of the cookie `name`.
+pub fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    let cookies = headers.typed_get::<Cookie>()?;
+    cookies.get(name).map(String::from)
+}
+
+/// Methods that must not change state, and so need no CSRF protection.
+pub fn is_safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Checks that the CSRF header repeats the CSRF cookie. A cross-site page can make the
+/// browser send the cookie but can't read it to set the header.
+pub fn verify_csrf(headers: &HeaderMap) -> Result<(), AuthError> {
+    let expected = cookie(headers, CSRF_COOKIE).ok_or(AuthError::InvalidCsrfToken)?;
+    let actual = headers
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AuthError::InvalidCsrfToken)?;
+
+    if expected.is_empty() || !constant_time_eq(expected.as_bytes(), actual.as_bytes()) {
+        return Err(AuthError::InvalidCsrfToken);
+    }
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_bearer_header_takes_precedence() {
+        let headers = headers(&[
+            ("authorization", "Bearer header-token"),
+            ("cookie", "access_token=cookie-token"),
+        ]);
+
+        let token = request_token(&headers, &Method::POST).unwrap();
+        assert_eq!(token.as_deref(), Some("header-token"));
+    }
+
+    #[test]
+    fn test_cookie_on_safe_method() {
+        let headers = headers(&[("cookie", "access_token=cookie-token")]);
+
+        let token = request_token(&headers, &Method::GET).unwrap();
+        assert_eq!(token.as_deref(), Some("cookie-token"));
+    }
+
+    #[test]
+    fn test_cookie_on_unsafe_method_requires_csrf() {
+        let without_header = headers(&[("cookie", "access_token=t; csrf_token=abc")]);
+        assert_eq!(
+            request_token(&without_header, &Method::POST).unwrap_err(),
+            AuthError::InvalidCsrfToken
+        );
+
+        let mismatch = headers(&[
+            ("cookie", "access_token=t; csrf_token=abc"),
+            ("x-csrf-token", "abd"),
+        ]);
+        assert_eq!(
+            request_token(&mismatch, &Method::DELETE).unwrap_err(),
+            AuthError::InvalidCsrfToken
+        );
+
+        let matching = headers(&[
+            ("cookie", "access_token=t; csrf_token=abc"),
+            ("x-csrf-token", "abc"),
+        ]);
+        let token = request_token(&matching, &Method::PUT).unwrap();
+        assert_eq!(token.as_deref(), Some("t"));
+    }
+
+    #[test]
+    fn test_no_credentials() {
+        let token = request_token(&HeaderMap::new(), &Method::POST).unwrap();
+        assert!(token.is_none());
+    }
+}
@@ -1,56 +1,421 @@
+use crate::claims::{Principal, TokenUse};
+use crate::cookies::request_token;
+use crate::introspection::IntrospectionClient;
+use crate::personal_tokens::is_personal_token;
+use crate::rejection::AuthError;
+use crate::tokens::TokenManager;
+use crate::AuthenticatedUser;
 use async_trait::async_trait;
 use axum::{
-    extract::{Extension, FromRequestParts},
-    http::{request::Parts, StatusCode},
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::request::Parts,
 };
-use axum_extra::TypedHeader;
-use headers::{authorization::Bearer, Authorization};
+use jsonwebtoken::errors::ErrorKind;
 use std::future::Future;
 use std::sync::Arc;
-use crate::AuthenticatedUser;
+
+/// Validates an access token or a personal access token and checks that it wasn't revoked.
+pub(crate) async fn authenticate(
+    token_manager: &TokenManager,
+    token: &str,
+) -> Result<AuthenticatedUser, AuthError> {
+    let user = if is_personal_token(token) {
+        authenticate_personal_token(token_manager, token).await?
+    } else {
+        authenticate_jwt(token_manager, token).await?
+    };
+
+    // Проверка, что пользователь существует и активен. Токены сервисов живут недолго
+    if user.is_service() {
+        return Ok(user);
+    }
+    let active = token_manager
+        .is_user_active(&user.id)
+        .await
+        .map_err(|_| AuthError::Unavailable)?;
+    if !active {
+        return Err(AuthError::InactiveUser);
+    }
+
+    Ok(user)
+}
+
+async fn authenticate_jwt(
+    token_manager: &TokenManager,
+    token: &str,
+) -> Result<AuthenticatedUser, AuthError> {
+    // Ключ мог смениться в bartender
+    token_manager
+        .refresh_keys(token)
+        .await
+        .map_err(|_| AuthError::Unavailable)?;
+
+    // Валидация токена
+    let claims = token_manager
+        .validate_token(token, TokenUse::Access)
+        .map_err(|err| match err.kind() {
+            ErrorKind::ExpiredSignature => AuthError::ExpiredToken,
+            _ => AuthError::InvalidToken,
+        })?;
+
+    // Проверка, что токен не отозван
+    let revoked = token_manager
+        .is_revoked(&claims)
+        .await
+        .map_err(|_| AuthError::Unavailable)?;
+    if revoked {
+        return Err(AuthError::RevokedToken);
+    }
+
+    Ok(AuthenticatedUser::from(claims))
+}
+
+/// Revoked personal access tokens are deleted, so an unknown token is just invalid.
+async fn authenticate_personal_token(
+    token_manager: &TokenManager,
+    token: &str,
+) -> Result<AuthenticatedUser, AuthError> {
+    let personal_token = token_manager
+        .find_personal_token(token)
+        .await
+        .map_err(|_| AuthError::Unavailable)?
+        .ok_or(AuthError::InvalidToken)?;
+
+    Ok(AuthenticatedUser {
+        id: personal_token.user_id,
+        roles: personal_token.roles,
+        scopes: personal_token.scopes,
+        principal: Principal::User,
+        session_id: None,
+    })
+}
+
+/// Validates the token with the `TokenManager` or the `IntrospectionClient` of the request.
+async fn authenticate_request(parts: &Parts, token: &str) -> Result<AuthenticatedUser, AuthError> {
+    // Локальная проверка через TokenManager или запрос к bartender
+    if let Some(token_manager) = parts.extensions.get::<Arc<TokenManager>>().cloned() {
+        return authenticate(&token_manager, token).await;
+    }
+    if let Some(client) = parts.extensions.get::<Arc<IntrospectionClient>>().cloned() {
+        return client.authenticate(token).await;
+    }
+
+    Err(AuthError::MissingValidator)
+}
 
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthenticatedUser
 where
     S: Sync + Send,
 {
-    type Rejection = (StatusCode, &'static str);
+    type Rejection = AuthError;
 
     fn from_request_parts(
         parts: &mut Parts,
-        state: &S,
+        _state: &S,
     ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
         Box::pin(async move {
-            // Получение Authorization: Bearer ...
-            let TypedHeader(Authorization(bearer)) =
-                TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
-                    .await
-                    .map_err(|_| {
-                        (
-                            StatusCode::UNAUTHORIZED,
-                            "Missing or invalid Authorization header",
-                        )
-                    })?;
-
-            let token = bearer.token();
-
-            // Получение TokenManager из Extension
-            let Extension(token_manager) =
-                Extension::<Arc<crate::tokens::TokenManager>>::from_request_parts(parts, state)
-                    .await
-                    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "TokenManager not found"))?;
-
-            // Валидация токена
-            let claims = token_manager
-                .validate_token(token)
-                .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token"))?;
-
-            // Сбор информации о пользователе
-            let user_id = claims.sub;
-
-            // TODO: Можно сходить в бд, проверив что user существует
-
-            Ok(AuthenticatedUser { id: user_id })
+            // Уже проверен AuthLayer
+            if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+                return Ok(user.clone());
+            }
+
+            // Получение Authorization: Bearer ... или cookie с токеном
+            let token =
+                request_token(&parts.headers, &parts.method)?.ok_or(AuthError::MissingToken)?;
+
+            authenticate_request(parts, &token).await
+        })
+    }
+}
+
+/// Anonymous requests give `None`. A request with an invalid token is still rejected,
+/// so that the client learns its token is no longer accepted.
+impl<S> OptionalFromRequestParts<S> for AuthenticatedUser
+where
+    S: Sync + Send,
+{
+    type Rejection = AuthError;
+
+    fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> impl Future<Output = Result<Option<Self>, Self::Rejection>> + Send {
+        Box::pin(async move {
+            if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+                return Ok(Some(user.clone()));
+            }
+
+            let Some(token) = request_token(&parts.headers, &parts.method)? else {
+                return Ok(None);
+            };
+
+            authenticate_request(parts, &token).await.map(Some)
         })
     }
 }
+
+/// Same as `Option<AuthenticatedUser>`, for handlers that prefer a named extractor.
+#[derive(Debug, Clone)]
+pub struct MaybeUser(pub Option<AuthenticatedUser>);
+
+impl<S> FromRequestParts<S> for MaybeUser
+where
+    S: Sync + Send,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user =
+            <AuthenticatedUser as OptionalFromRequestParts<S>>::from_request_parts(parts, state)
+                .await?;
+        Ok(MaybeUser(user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personal_tokens::{
+        generate_personal_token, PersonalToken, PersonalTokenError, PersonalTokenStore,
+    };
+    use crate::revocation::InMemoryRevocationStore;
+    use crate::test_util::{test_token_manager, test_user};
+    use crate::users::{UserLookup, UserLookupError};
+    use axum::http::Request;
+    use chrono::Duration;
+    use models::user::User;
+    use uuid::Uuid;
+
+    fn setup() -> (Arc<TokenManager>, User) {
+        let (manager, user) = setup_manager();
+        (Arc::new(manager), user)
+    }
+
+    fn setup_manager() -> (TokenManager, User) {
+        (test_token_manager(), test_user())
+    }
+
+    async fn extract(
+        manager: Arc<TokenManager>,
+        token: &str,
+    ) -> Result<AuthenticatedUser, AuthError> {
+        let (mut parts, _) = Request::builder()
+            .header("Authorization", format!("Bearer {}", token))
+            .extension(manager)
+            .body(())
+            .unwrap()
+            .into_parts();
+        <AuthenticatedUser as FromRequestParts<()>>::from_request_parts(&mut parts, &()).await
+    }
+
+    async fn extract_optional(
+        manager: Arc<TokenManager>,
+        token: Option<&str>,
+    ) -> Result<MaybeUser, AuthError> {
+        let mut request = Request::builder().extension(manager);
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        MaybeUser::from_request_parts(&mut parts, &()).await
+    }
+
+    /// Only `active_id` is an existing, active user.
+    struct SingleUser {
+        active_id: String,
+    }
+
+    #[async_trait]
+    impl UserLookup for SingleUser {
+        async fn is_active(&self, user_id: &str) -> Result<bool, UserLookupError> {
+            Ok(user_id == self.active_id)
+        }
+    }
+
+    /// Knows a single personal access token.
+    struct SingleToken {
+        token_hash: String,
+        personal_token: PersonalToken,
+    }
+
+    #[async_trait]
+    impl PersonalTokenStore for SingleToken {
+        async fn find(
+            &self,
+            token_hash: &str,
+        ) -> Result<Option<PersonalToken>, PersonalTokenError> {
+            Ok((token_hash == self.token_hash).then(|| self.personal_token.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_token_is_accepted() {
+        let (manager, user) = setup();
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        let authenticated = extract(manager, &token).await.unwrap();
+        assert_eq!(authenticated.id, user.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_is_rejected() {
+        let (manager, user) = setup();
+        let token = manager
+            .generate_refresh_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        let err = extract(manager, &token).await.unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_distinguished() {
+        let (manager, user) = setup();
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(-10))
+            .unwrap();
+
+        let err = extract(manager, &token).await.unwrap_err();
+        assert_eq!(err, AuthError::ExpiredToken);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_token_is_rejected() {
+        let (manager, _) = setup();
+
+        let err = extract(manager, "not.a.token").await.unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn test_revoked_token_is_rejected() {
+        let (manager, user) = setup_manager();
+        let manager = Arc::new(manager.with_revocation_store(
+            Arc::new(InMemoryRevocationStore::new()),
+            std::time::Duration::from_secs(60),
+        ));
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+        assert!(extract(manager.clone(), &token).await.is_ok());
+
+        let claims = manager.validate_token(&token, TokenUse::Access).unwrap();
+        manager.revoke(&claims).await.unwrap();
+
+        let err = extract(manager, &token).await.unwrap_err();
+        assert_eq!(err, AuthError::RevokedToken);
+    }
+
+    #[tokio::test]
+    async fn test_optional_user_without_token() {
+        let (manager, _) = setup();
+
+        let MaybeUser(user) = extract_optional(manager, None).await.unwrap();
+        assert!(user.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_optional_user_with_token() {
+        let (manager, user) = setup();
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        let MaybeUser(authenticated) = extract_optional(manager, Some(&token)).await.unwrap();
+        assert_eq!(authenticated.unwrap().id, user.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_optional_user_with_invalid_token_is_rejected() {
+        let (manager, _) = setup();
+
+        let err = extract_optional(manager, Some("not.a.token"))
+            .await
+            .unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn test_inactive_user_is_rejected() {
+        let (manager, user) = setup_manager();
+        let manager = Arc::new(manager.with_user_lookup(
+            Arc::new(SingleUser {
+                active_id: user.id.to_string(),
+            }),
+            std::time::Duration::from_secs(60),
+        ));
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+        assert!(extract(manager.clone(), &token).await.is_ok());
+
+        let other = User {
+            id: Uuid::new_v4(),
+            ..user
+        };
+        let token = manager
+            .generate_access_token(&other, &[], Duration::seconds(60))
+            .unwrap();
+        let err = extract(manager, &token).await.unwrap_err();
+        assert_eq!(err, AuthError::InactiveUser);
+    }
+
+    #[tokio::test]
+    async fn test_service_token_skips_user_lookup() {
+        let (manager, user) = setup_manager();
+        let manager = Arc::new(manager.with_user_lookup(
+            Arc::new(SingleUser {
+                active_id: user.id.to_string(),
+            }),
+            std::time::Duration::from_secs(60),
+        ));
+        let token = manager
+            .generate_service_token("billing", &[], Duration::seconds(60))
+            .unwrap();
+
+        let authenticated = extract(manager, &token).await.unwrap();
+        assert!(authenticated.is_service());
+    }
+
+    #[tokio::test]
+    async fn test_personal_access_token() {
+        let (manager, user) = setup_manager();
+        let (token, token_hash) = generate_personal_token();
+        let (expired, expired_hash) = generate_personal_token();
+        let personal_token = PersonalToken {
+            user_id: user.id.to_string(),
+            roles: user.roles.clone(),
+            scopes: vec!["tasks:read".to_string()],
+            expires_at: None,
+        };
+        let manager = Arc::new(manager.with_personal_token_store(Arc::new(SingleToken {
+            token_hash,
+            personal_token,
+        })));
+
+        let authenticated = extract(manager.clone(), &token).await.unwrap();
+        assert_eq!(authenticated.id, user.id.to_string());
+        assert!(authenticated.has_scope("tasks:read"));
+        assert!(!authenticated.has_scope("tasks:write"));
+
+        let err = extract(manager.clone(), &expired).await.unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+
+        let expired_manager = Arc::new(setup_manager().0.with_personal_token_store(Arc::new(
+            SingleToken {
+                token_hash: expired_hash,
+                personal_token: PersonalToken {
+                    user_id: user.id.to_string(),
+                    roles: vec![],
+                    scopes: vec![],
+                    expires_at: Some(chrono::Utc::now() - Duration::seconds(1)),
+                },
+            },
+        )));
+        let err = extract(expired_manager, &expired).await.unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+    }
+}
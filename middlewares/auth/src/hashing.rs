@@ -0,0 +1,24 @@
+use ring::digest::{digest, SHA256};
+
+/// Hash under which random tokens (personal access, refresh, reset tokens, ...) are stored.
+/// The tokens are random and long, so a fast hash is enough and allows lookups by hash.
+pub fn hash_token(token: &str) -> String {
+    hex(digest(&SHA256, token.as_bytes()).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("token"),
+            "3c469e9d6c5875d37a43f353d4f88e61fcf812c66eee3457465a40b0da4153e0"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::cache::{TtlCache, CACHE_CAPACITY};
+use crate::claims::Principal;
+use crate::rejection::AuthError;
+use crate::{AuthenticatedUser, IntrospectionState};
+use chrono::Utc;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+pub type IntrospectionError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Deserialize)]
+struct IntrospectionResponse {
+    active: bool,
+    sub: Option<String>,
+    exp: Option<i64>,
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Vec<String>,
+    #[serde(default)]
+    scope: String,
+    #[serde(default)]
+    roles: Vec<String>,
+    token_use: Option<String>,
+    #[serde(default)]
+    principal: Principal,
+    sid: Option<String>,
+}
+
+/// Validates access tokens by asking bartender (`/api/auth/introspect`, RFC 7662) instead of
+/// checking them locally, so the service needs no key material at all.
+///
+/// Active tokens are cached until they expire: a token revoked in the meantime keeps working
+/// on this service until its `exp`. Tokens without `exp`, i.e. personal access tokens, are
+/// introspected on every request.
+pub struct IntrospectionClient {
+    http: reqwest::Client,
+    state: IntrospectionState,
+    cache: TtlCache<AuthenticatedUser>,
+}
+
+impl IntrospectionClient {
+    pub fn new(state: IntrospectionState) -> Self {
+        Self::with_http_client(state, crate::http_client())
+    }
+
+    pub fn with_http_client(state: IntrospectionState, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            state,
+            cache: TtlCache::new(CACHE_CAPACITY),
+        }
+    }
+
+    /// User of an active access token, `None` for invalid, expired, revoked or refresh tokens.
+    pub async fn introspect(
+        &self,
+        token: &str,
+    ) -> Result<Option<AuthenticatedUser>, IntrospectionError> {
+        if let Some(user) = self.cache.get(token) {
+            return Ok(Some(user));
+        }
+        let now = Utc::now().timestamp();
+
+        let response: IntrospectionResponse = self
+            .http
+            .post(&self.state.url)
+            .basic_auth(&self.state.client_id, Some(&self.state.client_secret))
+            .form(&[("token", token)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let Some(sub) = response.sub else {
+            return Ok(None);
+        };
+        if !response.active
+            || response.token_use.as_deref() != Some("access")
+            || response.exp.is_some_and(|exp| exp <= now)
+            || response.iss.as_deref() != Some(self.state.issuer.as_str())
+            || !response
+                .aud
+                .iter()
+                .any(|aud| self.state.audience.contains(aud))
+        {
+            return Ok(None);
+        }
+
+        let user = AuthenticatedUser {
+            id: sub,
+            roles: response.roles,
+            scopes: response
+                .scope
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            principal: response.principal,
+            session_id: response.sid,
+        };
+        if let Some(exp) = response.exp {
+            let expires_at = Instant::now() + Duration::from_secs((exp - now) as u64);
+            self.cache.insert(token, user.clone(), expires_at);
+        }
+        Ok(Some(user))
+    }
+
+    pub(crate) async fn authenticate(&self, token: &str) -> Result<AuthenticatedUser, AuthError> {
+        match self.introspect(token).await {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(AuthError::InvalidToken),
+            Err(_) => Err(AuthError::Unavailable),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::post;
+    use axum::{Form, Json, Router};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// `Basic base64("todo:secret")`
+    const CREDENTIALS: &str = "Basic dG9kbzpzZWNyZXQ=";
+
+    /// Stub of bartender's introspection endpoint: `good` and `refresh` are known tokens,
+    /// `billing` is issued to another service.
+    async fn stub_server() -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let exp = Utc::now().timestamp() + 3600;
+        let app = Router::new().route(
+            "/api/auth/introspect",
+            post(
+                move |headers: HeaderMap, Form(form): Form<HashMap<String, String>>| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if headers.get("authorization").map(|value| value.as_bytes())
+                            != Some(CREDENTIALS.as_bytes())
+                        {
+                            return StatusCode::UNAUTHORIZED.into_response();
+                        }
+                        let response: Value = match form.get("token").map(String::as_str) {
+                            Some("good") => json!({
+                                "active": true,
+                                "sub": "user-1",
+                                "exp": exp,
+                                "iss": "bartender",
+                                "aud": ["todo"],
+                                "scope": "tasks:read tasks:write",
+                                "roles": ["user"],
+                                "token_use": "access",
+                            }),
+                            Some("pat_ci") => json!({
+                                "active": true,
+                                "sub": "user-1",
+                                "iss": "bartender",
+                                "aud": ["todo"],
+                                "scope": "tasks:read",
+                                "token_use": "access",
+                            }),
+                            Some("billing") => json!({
+                                "active": true,
+                                "sub": "user-1",
+                                "exp": exp,
+                                "iss": "bartender",
+                                "aud": ["billing"],
+                                "token_use": "access",
+                            }),
+                            Some("refresh") => json!({
+                                "active": true,
+                                "sub": "user-1",
+                                "exp": exp,
+                                "iss": "bartender",
+                                "aud": ["todo"],
+                                "token_use": "refresh",
+                            }),
+                            _ => json!({ "active": false }),
+                        };
+                        Json(response).into_response()
+                    }
+                },
+            ),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}/api/auth/introspect", address), calls)
+    }
+
+    fn client(url: String) -> IntrospectionClient {
+        client_with_secret(url, "secret")
+    }
+
+    fn client_with_secret(url: String, client_secret: &str) -> IntrospectionClient {
+        IntrospectionClient::new(IntrospectionState {
+            url,
+            client_id: "todo".to_string(),
+            client_secret: client_secret.to_string(),
+            issuer: "bartender".to_string(),
+            audience: vec!["todo".to_string()],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_active_token_is_cached() {
+        let (url, calls) = stub_server().await;
+        let client = client(url);
+
+        let user = client.introspect("good").await.unwrap().unwrap();
+        assert_eq!(user.id, "user-1");
+        assert!(user.has_role("user"));
+        assert!(user.has_scope("tasks:write"));
+
+        client.introspect("good").await.unwrap().unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_inactive_token_is_not_cached() {
+        let (url, calls) = stub_server().await;
+        let client = client(url);
+
+        assert!(client.introspect("bad").await.unwrap().is_none());
+        assert!(client.introspect("bad").await.unwrap().is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_token_without_expiration_is_not_cached() {
+        let (url, calls) = stub_server().await;
+        let client = client(url);
+
+        let user = client.introspect("pat_ci").await.unwrap().unwrap();
+        assert!(user.has_scope("tasks:read"));
+        client.introspect("pat_ci").await.unwrap().unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_is_rejected() {
+        let (url, _) = stub_server().await;
+        let client = client(url);
+
+        let err = client.authenticate("refresh").await.unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn test_token_of_other_audience_is_rejected() {
+        let (url, _) = stub_server().await;
+        let client = client(url);
+
+        let err = client.authenticate("billing").await.unwrap_err();
+        assert_eq!(err, AuthError::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_client_credentials() {
+        let (url, _) = stub_server().await;
+        let client = client_with_secret(url, "wrong");
+
+        let err = client.authenticate("good").await.unwrap_err();
+        assert_eq!(err, AuthError::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server() {
+        let client = client("http://127.0.0.1:1/api/auth/introspect".to_string());
+
+        let err = client.authenticate("good").await.unwrap_err();
+        assert_eq!(err, AuthError::Unavailable);
+    }
+}
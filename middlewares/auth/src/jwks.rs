@@ -0,0 +1,237 @@
+use crate::keys::JwtKey;
+use jsonwebtoken::jwk::JwkSet;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+pub type JwksError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Shortest time between two fetches, so that tokens with made-up `kid`s can't flood
+/// bartender with requests.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Verification keys fetched from bartender's `/.well-known/jwks.json`, so that a service
+/// needs no key material in its config and follows `bartender rotate-key` by itself.
+///
+/// The keys are fetched again when a token names a `kid` not seen yet, and once they are
+/// older than `max_age`, so that keys bartender dropped stop being accepted.
+pub struct JwksClient {
+    http: reqwest::Client,
+    url: String,
+    max_age: Duration,
+    min_refresh_interval: Duration,
+    cache: RwLock<CachedKeys>,
+}
+
+#[derive(Default)]
+struct CachedKeys {
+    keys: Vec<JwtKey>,
+    fetched_at: Option<Instant>,
+}
+
+impl JwksClient {
+    /// `url` is the full JWKS endpoint, e.g. `http://bartender:3001/.well-known/jwks.json`.
+    pub fn new(url: impl Into<String>, max_age: Duration) -> Self {
+        Self::with_http_client(url, max_age, crate::http_client())
+    }
+
+    pub fn with_http_client(
+        url: impl Into<String>,
+        max_age: Duration,
+        http: reqwest::Client,
+    ) -> Self {
+        Self {
+            http,
+            url: url.into(),
+            max_age,
+            min_refresh_interval: MIN_REFRESH_INTERVAL,
+            cache: RwLock::new(CachedKeys::default()),
+        }
+    }
+
+    /// Cached key with the given `kid`.
+    pub fn key(&self, kid: Option<&str>) -> Option<JwtKey> {
+        let cache = self.cache.read().unwrap();
+        cache.keys.iter().find(|key| key.kid() == kid).cloned()
+    }
+
+    /// Fetches the keys and replaces the cached ones. Keys of other algorithms than RS256
+    /// and EdDSA are skipped.
+    pub async fn refresh(&self) -> Result<(), JwksError> {
+        self.cache.write().unwrap().fetched_at = Some(Instant::now());
+
+        let jwks: JwkSet = self
+            .http
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let keys = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| JwtKey::from_jwk(jwk).ok())
+            .collect();
+
+        self.cache.write().unwrap().keys = keys;
+        Ok(())
+    }
+
+    /// Fetches the keys when they are older than `max_age`, or lack `kid` and weren't
+    /// fetched in the last few seconds.
+    pub(crate) async fn refresh_if_stale(&self, kid: Option<&str>) -> Result<(), JwksError> {
+        let stale = {
+            let cache = self.cache.read().unwrap();
+            match cache.fetched_at.map(|fetched_at| fetched_at.elapsed()) {
+                None => true,
+                Some(age) if age >= self.max_age => true,
+                Some(age) => {
+                    age >= self.min_refresh_interval
+                        && !cache.keys.iter().any(|key| key.kid() == kid)
+                }
+            }
+        };
+        if stale {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claims::TokenUse;
+    use crate::tokens::TokenManager;
+    use crate::{JWTState, JWTVerifierState};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use models::user::User;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    const ED_PRIVATE: &[u8] = include_bytes!("../testdata/ed25519_private.pem");
+    const RSA_PRIVATE: &[u8] = include_bytes!("../testdata/rsa_private.pem");
+
+    fn issuer(key: JwtKey) -> TokenManager {
+        TokenManager::new(JWTState {
+            key,
+            retired_keys: vec![],
+            issuer: "bartender".to_string(),
+            audience: vec!["todo".to_string()],
+            leeway: 0,
+            access_token_expiration: 3600,
+            refresh_token_expiration: 604800,
+        })
+    }
+
+    fn verifier(jwks: Arc<JwksClient>) -> TokenManager {
+        TokenManager::verifier(JWTVerifierState {
+            keys: vec![],
+            issuer: "bartender".to_string(),
+            audience: vec!["todo".to_string()],
+            leeway: 0,
+        })
+        .with_jwks(jwks)
+    }
+
+    fn token(issuer: &TokenManager) -> String {
+        let user = User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: String::new(),
+            roles: vec![],
+        };
+        issuer
+            .generate_access_token(&user, &[], chrono::Duration::seconds(60))
+            .unwrap()
+    }
+
+    /// Stub of bartender's JWKS endpoint, serving the JWKS of `issuer` until it is replaced.
+    async fn stub_server(issuer: &TokenManager) -> (String, Arc<Mutex<JwkSet>>, Arc<AtomicUsize>) {
+        let jwks = Arc::new(Mutex::new(issuer.jwks()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (served, counter) = (jwks.clone(), calls.clone());
+        let app = Router::new().route(
+            "/.well-known/jwks.json",
+            get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let jwks = served.lock().unwrap().clone();
+                async move { Json(jwks) }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (
+            format!("http://{}/.well-known/jwks.json", address),
+            jwks,
+            calls,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_token_verified_with_fetched_key() {
+        let issuer = issuer(JwtKey::from_ed_pem(ED_PRIVATE).unwrap().with_kid("first"));
+        let (url, _, calls) = stub_server(&issuer).await;
+        let verifier = verifier(Arc::new(JwksClient::new(url, Duration::from_secs(300))));
+        let token = token(&issuer);
+
+        assert!(verifier.validate_token(&token, TokenUse::Access).is_err());
+        verifier.refresh_keys(&token).await.unwrap();
+        assert!(verifier.validate_token(&token, TokenUse::Access).is_ok());
+
+        verifier.refresh_keys(&token).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rotated_key_is_fetched() {
+        let first = issuer(JwtKey::from_ed_pem(ED_PRIVATE).unwrap().with_kid("first"));
+        let (url, jwks, calls) = stub_server(&first).await;
+        let mut client = JwksClient::new(url, Duration::from_secs(300));
+        client.min_refresh_interval = Duration::ZERO;
+        let verifier = verifier(Arc::new(client));
+        verifier.refresh_keys(&token(&first)).await.unwrap();
+
+        let second = issuer(
+            JwtKey::from_rsa_pem(RSA_PRIVATE)
+                .unwrap()
+                .with_kid("second"),
+        );
+        *jwks.lock().unwrap() = second.jwks();
+        let token = token(&second);
+        verifier.refresh_keys(&token).await.unwrap();
+
+        assert!(verifier.validate_token(&token, TokenUse::Access).is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_kid_does_not_refetch_right_away() {
+        let issuer = issuer(JwtKey::from_ed_pem(ED_PRIVATE).unwrap().with_kid("first"));
+        let (url, _, calls) = stub_server(&issuer).await;
+        let client = JwksClient::new(url, Duration::from_secs(300));
+        client.refresh().await.unwrap();
+
+        client.refresh_if_stale(Some("made-up")).await.unwrap();
+        client.refresh_if_stale(Some("made-up")).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(client.key(Some("first")).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server() {
+        let client = JwksClient::new(
+            "http://127.0.0.1:1/.well-known/jwks.json",
+            Duration::from_secs(300),
+        );
+
+        assert!(client.refresh().await.is_err());
+        assert!(client.key(None).is_none());
+    }
+}
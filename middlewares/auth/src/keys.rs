@@ -0,0 +1,292 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::rsa::PublicKeyComponents;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+
+/// Key material used by `TokenManager` to sign and verify tokens.
+///
+/// Keys built from a private key (or an HMAC secret) can both sign and verify.
+/// Keys built from a public key can only verify, which is all a downstream
+/// service needs.
+#[derive(Clone)]
+pub struct JwtKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+impl JwtKey {
+    /// HS256 key from a shared secret. Never published in the JWKS.
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            encoding_key: Some(EncodingKey::from_secret(secret)),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    /// RS256 key from an RSA private key in PEM (PKCS#1 or PKCS#8).
+    pub fn from_rsa_pem(key: &[u8]) -> Result<Self, JwtError> {
+        let pem = parse_pem(key)?;
+        let key_pair = match pem.tag() {
+            "RSA PRIVATE KEY" => RsaKeyPair::from_der(pem.contents())?,
+            "PRIVATE KEY" => RsaKeyPair::from_pkcs8(pem.contents())?,
+            _ => return Err(ErrorKind::InvalidKeyFormat.into()),
+        };
+        let public = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+
+        Ok(Self {
+            kid: None,
+            algorithm: Algorithm::RS256,
+            encoding_key: Some(EncodingKey::from_rsa_pem(key)?),
+            decoding_key: DecodingKey::from_rsa_raw_components(&public.n, &public.e),
+            jwk: Some(Jwk {
+                common: public_key_parameters(KeyAlgorithm::RS256),
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(&public.n),
+                    e: URL_SAFE_NO_PAD.encode(&public.e),
+                }),
+            }),
+        })
+    }
+
+    /// EdDSA key from an Ed25519 private key in PEM (PKCS#8).
+    pub fn from_ed_pem(key: &[u8]) -> Result<Self, JwtError> {
+        let pem = parse_pem(key)?;
+        if pem.tag() != "PRIVATE KEY" {
+            return Err(ErrorKind::InvalidKeyFormat.into());
+        }
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())?;
+        let x = URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref());
+
+        Ok(Self {
+            kid: None,
+            algorithm: Algorithm::EdDSA,
+            encoding_key: Some(EncodingKey::from_ed_pem(key)?),
+            decoding_key: DecodingKey::from_ed_components(&x)?,
+            jwk: Some(Jwk {
+                common: public_key_parameters(KeyAlgorithm::EdDSA),
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            }),
+        })
+    }
+
+    /// Verification-only RS256 key from an RSA public key in PEM.
+    pub fn from_rsa_public_pem(key: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self {
+            kid: None,
+            algorithm: Algorithm::RS256,
+            encoding_key: None,
+            decoding_key: DecodingKey::from_rsa_pem(key)?,
+            jwk: None,
+        })
+    }
+
+    /// Verification-only EdDSA key from an Ed25519 public key in PEM.
+    pub fn from_ed_public_pem(key: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self {
+            kid: None,
+            algorithm: Algorithm::EdDSA,
+            encoding_key: None,
+            decoding_key: DecodingKey::from_ed_pem(key)?,
+            jwk: None,
+        })
+    }
+
+    /// Same key without its signing part.
+    pub fn verify_only(mut self) -> Self {
+        self.encoding_key = None;
+        self
+    }
+
+    /// Verification-only key from a JWK of bartender's JWKS. Only RS256 and EdDSA keys are
+    /// accepted, so a JWKS can't slip in a key of another algorithm.
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        let algorithm = match &jwk.algorithm {
+            AlgorithmParameters::RSA(_) => Algorithm::RS256,
+            AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519 => {
+                Algorithm::EdDSA
+            }
+            _ => return Err(ErrorKind::InvalidKeyFormat.into()),
+        };
+        let expected = match algorithm {
+            Algorithm::RS256 => KeyAlgorithm::RS256,
+            _ => KeyAlgorithm::EdDSA,
+        };
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|key_algorithm| key_algorithm != expected)
+        {
+            return Err(ErrorKind::InvalidAlgorithm.into());
+        }
+
+        Ok(Self {
+            kid: jwk.common.key_id.clone(),
+            algorithm,
+            encoding_key: None,
+            decoding_key: DecodingKey::from_jwk(jwk)?,
+            jwk: None,
+        })
+    }
+
+    /// Sets the `kid` written to the header of tokens signed with this key.
+    pub fn with_kid(mut self, kid: impl Into<String>) -> Self {
+        let kid = kid.into();
+        if let Some(jwk) = self.jwk.as_mut() {
+            jwk.common.key_id = Some(kid.clone());
+        }
+        self.kid = Some(kid);
+        self
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn can_sign(&self) -> bool {
+        self.encoding_key.is_some()
+    }
+
+    /// Public part of the key as a JWK. `None` for secrets and verification-only keys.
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+
+    pub(crate) fn encoding_key(&self) -> Result<&EncodingKey, JwtError> {
+        self.encoding_key
+            .as_ref()
+            .ok_or_else(|| ErrorKind::InvalidKeyFormat.into())
+    }
+
+    pub(crate) fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+}
+
+fn parse_pem(key: &[u8]) -> Result<pem::Pem, JwtError> {
+    pem::parse(key).map_err(|_| ErrorKind::InvalidKeyFormat.into())
+}
+
+fn public_key_parameters(algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSA_PRIVATE: &[u8] = include_bytes!("../testdata/rsa_private.pem");
+    const RSA_PUBLIC: &[u8] = include_bytes!("../testdata/rsa_public.pem");
+    const ED_PRIVATE: &[u8] = include_bytes!("../testdata/ed25519_private.pem");
+    const ED_PUBLIC: &[u8] = include_bytes!("../testdata/ed25519_public.pem");
+
+    #[test]
+    fn test_secret_key_is_not_published() {
+        let key = JwtKey::from_secret(b"secret");
+        assert_eq!(key.algorithm(), Algorithm::HS256);
+        assert!(key.can_sign());
+        assert!(key.jwk().is_none());
+    }
+
+    #[test]
+    fn test_rsa_private_key() {
+        let key = JwtKey::from_rsa_pem(RSA_PRIVATE).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::RS256);
+        assert!(key.can_sign());
+
+        let jwk = key.jwk().expect("RSA key must have a JWK");
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(_)));
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert!(DecodingKey::from_jwk(jwk).is_ok());
+    }
+
+    #[test]
+    fn test_ed_private_key() {
+        let key = JwtKey::from_ed_pem(ED_PRIVATE).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::EdDSA);
+        assert!(key.can_sign());
+
+        match &key.jwk().expect("Ed25519 key must have a JWK").algorithm {
+            AlgorithmParameters::OctetKeyPair(params) => {
+                assert_eq!(params.curve, EllipticCurve::Ed25519);
+                assert_eq!(URL_SAFE_NO_PAD.decode(&params.x).unwrap().len(), 32);
+            }
+            _ => panic!("Expected OKP parameters"),
+        }
+    }
+
+    #[test]
+    fn test_public_keys_cannot_sign() {
+        let rsa = JwtKey::from_rsa_public_pem(RSA_PUBLIC).unwrap();
+        let ed = JwtKey::from_ed_public_pem(ED_PUBLIC).unwrap();
+        assert!(!rsa.can_sign());
+        assert!(!ed.can_sign());
+        assert!(rsa.encoding_key().is_err());
+    }
+
+    #[test]
+    fn test_verify_only_key_cannot_sign() {
+        let key = JwtKey::from_secret(b"secret").verify_only();
+        assert!(!key.can_sign());
+        assert_eq!(key.algorithm(), Algorithm::HS256);
+    }
+
+    #[test]
+    fn test_kid_is_published() {
+        let key = JwtKey::from_ed_pem(ED_PRIVATE).unwrap().with_kid("2025-01");
+        assert_eq!(key.kid(), Some("2025-01"));
+        assert_eq!(key.jwk().unwrap().common.key_id.as_deref(), Some("2025-01"));
+    }
+
+    #[test]
+    fn test_key_from_jwk() {
+        let key = JwtKey::from_ed_pem(ED_PRIVATE).unwrap().with_kid("2025-01");
+        let public = JwtKey::from_jwk(key.jwk().unwrap()).unwrap();
+        assert_eq!(public.kid(), Some("2025-01"));
+        assert_eq!(public.algorithm(), Algorithm::EdDSA);
+        assert!(!public.can_sign());
+
+        let mut jwk = JwtKey::from_rsa_pem(RSA_PRIVATE)
+            .unwrap()
+            .jwk()
+            .unwrap()
+            .clone();
+        assert_eq!(
+            JwtKey::from_jwk(&jwk).unwrap().algorithm(),
+            Algorithm::RS256
+        );
+        jwk.common.key_algorithm = Some(KeyAlgorithm::RS512);
+        assert!(JwtKey::from_jwk(&jwk).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_type() {
+        assert!(JwtKey::from_rsa_pem(ED_PRIVATE).is_err());
+        assert!(JwtKey::from_ed_pem(RSA_PRIVATE).is_err());
+        assert!(JwtKey::from_rsa_pem(b"not a pem").is_err());
+    }
+}
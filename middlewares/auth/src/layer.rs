@@ -0,0 +1,271 @@
+use crate::cookies::request_token;
+use crate::extractor::authenticate;
+use crate::introspection::IntrospectionClient;
+use crate::rejection::AuthError;
+use crate::tokens::TokenManager;
+use crate::AuthenticatedUser;
+use axum::extract::Request;
+use axum::http::Extensions;
+use axum::response::{IntoResponse, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Requires a valid access token on every route of the wrapped `Router`, except the
+/// explicitly allowed public ones. Tokens are read from `Authorization: Bearer` or from the
+/// access token cookie, and checked locally by a `TokenManager` or remotely by an
+/// `IntrospectionClient`.
+///
+/// The authenticated user is inserted into the request extensions, so handlers can take
+/// `AuthenticatedUser`, `Extension<AuthenticatedUser>` or any guard built on them. Public
+/// paths can still take `Option<AuthenticatedUser>` or `MaybeUser`.
+#[derive(Clone)]
+pub struct AuthLayer {
+    authenticator: Authenticator,
+    public_paths: Arc<PublicPaths>,
+}
+
+#[derive(Clone)]
+enum Authenticator {
+    Local(Arc<TokenManager>),
+    Remote(Arc<IntrospectionClient>),
+}
+
+impl Authenticator {
+    async fn authenticate(&self, token: &str) -> Result<AuthenticatedUser, AuthError> {
+        match self {
+            Authenticator::Local(token_manager) => authenticate(token_manager, token).await,
+            Authenticator::Remote(client) => client.authenticate(token).await,
+        }
+    }
+
+    /// Lets extractors validate tokens themselves, e.g. `Option<AuthenticatedUser>` on a
+    /// public path.
+    fn insert_into(&self, extensions: &mut Extensions) {
+        match self {
+            Authenticator::Local(token_manager) => {
+                extensions.insert(token_manager.clone());
+            }
+            Authenticator::Remote(client) => {
+                extensions.insert(client.clone());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct PublicPaths {
+    exact: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl PublicPaths {
+    fn contains(&self, path: &str) -> bool {
+        self.exact.iter().any(|public| public == path)
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+impl AuthLayer {
+    pub fn new(token_manager: Arc<TokenManager>) -> Self {
+        Self::with_authenticator(Authenticator::Local(token_manager))
+    }
+
+    pub fn remote(client: Arc<IntrospectionClient>) -> Self {
+        Self::with_authenticator(Authenticator::Remote(client))
+    }
+
+    fn with_authenticator(authenticator: Authenticator) -> Self {
+        Self {
+            authenticator,
+            public_paths: Arc::new(PublicPaths::default()),
+        }
+    }
+
+    /// Allows requests to exactly `path` without a token.
+    pub fn public_path(mut self, path: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.public_paths)
+            .exact
+            .push(path.into());
+        self
+    }
+
+    /// Allows requests to every path starting with `prefix` without a token.
+    pub fn public_prefix(mut self, prefix: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.public_paths)
+            .prefixes
+            .push(prefix.into());
+        self
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            authenticator: self.authenticator.clone(),
+            public_paths: self.public_paths.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    authenticator: Authenticator,
+    public_paths: Arc<PublicPaths>,
+}
+
+impl<S> Service<Request> for AuthService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        // The clone may not be ready, keep the service polled by `poll_ready`
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        self.authenticator.insert_into(request.extensions_mut());
+        if self.public_paths.contains(request.uri().path()) {
+            return Box::pin(inner.call(request));
+        }
+
+        let authenticator = self.authenticator.clone();
+        Box::pin(async move {
+            let token = match request_token(request.headers(), request.method()) {
+                Ok(Some(token)) => token,
+                Ok(None) => return Ok(AuthError::MissingToken.into_response()),
+                Err(rejection) => return Ok(rejection.into_response()),
+            };
+
+            match authenticator.authenticate(&token).await {
+                Ok(user) => {
+                    request.extensions_mut().insert(user);
+                    inner.call(request).await
+                }
+                Err(rejection) => Ok(rejection.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_token_manager, TestToken};
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use tower::ServiceExt;
+
+    fn setup() -> Router {
+        let token_manager = Arc::new(test_token_manager());
+        Router::new()
+            .route("/docs", get(|| async { "docs" }))
+            .route(
+                "/public/page",
+                get(|user: Option<AuthenticatedUser>| async move {
+                    user.map_or("anonymous".to_string(), |user| user.id)
+                }),
+            )
+            .route(
+                "/private",
+                get(|Extension(user): Extension<AuthenticatedUser>| async move { user.id }),
+            )
+            .layer(
+                AuthLayer::new(token_manager)
+                    .public_path("/docs")
+                    .public_prefix("/public/"),
+            )
+    }
+
+    async fn get_status(router: Router, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().uri(uri);
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn test_public_paths_are_open() {
+        let router = setup();
+        assert_eq!(
+            get_status(router.clone(), "/docs", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_status(router, "/public/page", None).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_private_path_requires_token() {
+        let router = setup();
+        assert_eq!(
+            get_status(router.clone(), "/private", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_status(router, "/private", Some("invalid")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_public_path_accepts_optional_user() {
+        let router = setup();
+        let token = TestToken::new().build();
+
+        assert_eq!(
+            get_status(router.clone(), "/public/page", Some(&token)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_status(router, "/public/page", Some("invalid")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_path_is_private() {
+        let router = setup();
+        assert_eq!(
+            get_status(router, "/docs/other", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_is_inserted_into_extensions() {
+        let router = setup();
+        let token = TestToken::new().build();
+
+        assert_eq!(
+            get_status(router, "/private", Some(&token)).await,
+            StatusCode::OK
+        );
+    }
+}
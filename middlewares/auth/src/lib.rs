@@ -1,14 +1,110 @@
 pub mod tokens;
+mod cache;
 pub mod claims;
+pub mod cookies;
 pub mod extractor;
+pub mod hashing;
+pub mod introspection;
+pub mod jwks;
+pub mod keys;
+pub mod rejection;
+pub mod layer;
+pub mod personal_tokens;
+pub mod revocation;
+pub mod roles;
+pub mod scopes;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod users;
+
+use crate::claims::{Claims, Principal};
+use crate::keys::JwtKey;
+use std::time::Duration;
 
 pub struct JWTState {
-    pub secret: String,
+    /// Key used to sign new tokens
+    pub key: JwtKey,
+    /// Previous keys, still accepted for verification until their tokens expire
+    pub retired_keys: Vec<JwtKey>,
+    /// `iss` of issued tokens, the only issuer accepted on validation
+    pub issuer: String,
+    /// `aud` of issued tokens. A token is accepted if it shares at least one audience
+    pub audience: Vec<String>,
+    /// Allowed clock skew in seconds for `exp` and `nbf`
+    pub leeway: u64,
     pub access_token_expiration: u64,
     pub refresh_token_expiration: u64,
 }
 
+/// Settings of a service that only verifies tokens issued by bartender.
+pub struct JWTVerifierState {
+    /// Keys bartender signs and signed with. Empty when they all come from the JWKS
+    pub keys: Vec<JwtKey>,
+    /// The only accepted `iss`
+    pub issuer: String,
+    /// Names of the service. A token is accepted if it shares at least one audience
+    pub audience: Vec<String>,
+    /// Allowed clock skew in seconds for `exp` and `nbf`
+    pub leeway: u64,
+}
+
+/// Settings of a service that has its tokens validated by bartender.
+pub struct IntrospectionState {
+    /// Full introspection endpoint, e.g. `http://bartender:3001/api/auth/introspect`
+    pub url: String,
+    /// Service client the service authenticates to the endpoint with
+    pub client_id: String,
+    pub client_secret: String,
+    /// The only accepted `iss`
+    pub issuer: String,
+    /// Names of the service. A token is accepted if it shares at least one audience
+    pub audience: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
+    /// User id, or client_id of a service
     pub id: String,
+    pub roles: Vec<String>,
+    pub scopes: Vec<String>,
+    pub principal: Principal,
+    /// Login session the token belongs to. `None` for personal access tokens and services
+    pub session_id: Option<String>,
+}
+
+impl From<Claims> for AuthenticatedUser {
+    fn from(claims: Claims) -> Self {
+        Self {
+            scopes: claims.scopes(),
+            id: claims.sub,
+            roles: claims.roles,
+            principal: claims.principal,
+            session_id: claims.sid,
+        }
+    }
+}
+
+impl AuthenticatedUser {
+    /// Whether the token was issued to a service client rather than a person.
+    pub fn is_service(&self) -> bool {
+        self.principal == Principal::Service
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// Client for calls to bartender. Requests waiting on them fail once the timeouts pass,
+/// instead of hanging while bartender does.
+pub(crate) fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(2))
+        .timeout(Duration::from_secs(5))
+        .build()
+        .expect("Failed to build HTTP client")
 }
@@ -0,0 +1,66 @@
+use crate::hashing::hash_token;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+
+pub type PersonalTokenError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Personal access tokens start with this prefix, which tells them apart from JWTs.
+pub const PERSONAL_TOKEN_PREFIX: &str = "pat_";
+
+/// Random bytes of a personal access token.
+const TOKEN_BYTES: usize = 32;
+
+/// Personal access token found by its hash.
+#[derive(Debug, Clone)]
+pub struct PersonalToken {
+    pub user_id: String,
+    pub roles: Vec<String>,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Personal access tokens are stored hashed: the plain token is shown once, at creation.
+#[async_trait]
+pub trait PersonalTokenStore: Send + Sync {
+    /// Token with this `hash_token` hash, `None` when it doesn't exist or was revoked.
+    async fn find(&self, token_hash: &str) -> Result<Option<PersonalToken>, PersonalTokenError>;
+}
+
+pub fn is_personal_token(token: &str) -> bool {
+    token.starts_with(PERSONAL_TOKEN_PREFIX)
+}
+
+/// New random token, to be returned to the user, and its hash, to be stored.
+pub fn generate_personal_token() -> (String, String) {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("Failed to generate random bytes");
+    let token = format!("{}{}", PERSONAL_TOKEN_PREFIX, URL_SAFE_NO_PAD.encode(bytes));
+    let token_hash = hash_token(&token);
+    (token, token_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_token() {
+        let (token, token_hash) = generate_personal_token();
+        assert!(is_personal_token(&token));
+        assert_eq!(hash_token(&token), token_hash);
+        assert_eq!(token_hash.len(), 64);
+
+        let (other, _) = generate_personal_token();
+        assert_ne!(token, other);
+    }
+
+    #[test]
+    fn test_jwt_is_not_personal_token() {
+        assert!(!is_personal_token("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+    }
+}
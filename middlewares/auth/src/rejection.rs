@@ -0,0 +1,166 @@
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+
+/// Why a request was not authenticated or not authorized.
+///
+/// Rendered as the usual `{"message", "details"}` JSON error with the RFC 6750 error code in
+/// `details.error`, plus a `WWW-Authenticate` challenge for token errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No `Authorization: Bearer` header nor access token cookie
+    MissingToken,
+    /// Malformed token, bad signature, wrong issuer, audience or token type
+    InvalidToken,
+    ExpiredToken,
+    RevokedToken,
+    /// The token is valid but its user was deleted or disabled
+    InactiveUser,
+    /// Space-separated scopes the route requires
+    InsufficientScope(String),
+    InsufficientRole,
+    /// The access token cookie came without a matching CSRF header
+    InvalidCsrfToken,
+    /// Neither `TokenManager` nor `IntrospectionClient` is available to the request
+    MissingValidator,
+    /// The revocation store or the introspection endpoint failed
+    Unavailable,
+}
+
+impl AuthError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::MissingToken
+            | AuthError::InvalidToken
+            | AuthError::ExpiredToken
+            | AuthError::RevokedToken
+            | AuthError::InactiveUser => StatusCode::UNAUTHORIZED,
+            AuthError::InsufficientScope(_)
+            | AuthError::InsufficientRole
+            | AuthError::InvalidCsrfToken => StatusCode::FORBIDDEN,
+            AuthError::MissingValidator => StatusCode::INTERNAL_SERVER_ERROR,
+            AuthError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// RFC 6750 error code. A request without credentials gets none.
+    pub fn error_code(&self) -> Option<&'static str> {
+        match self {
+            AuthError::InvalidToken
+            | AuthError::ExpiredToken
+            | AuthError::RevokedToken
+            | AuthError::InactiveUser => Some("invalid_token"),
+            AuthError::InsufficientScope(_) => Some("insufficient_scope"),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            AuthError::MissingToken => "Missing access token",
+            AuthError::InvalidToken => "The access token is malformed or invalid",
+            AuthError::ExpiredToken => "The access token expired",
+            AuthError::RevokedToken => "The access token has been revoked",
+            AuthError::InactiveUser => "The user no longer exists or is disabled",
+            AuthError::InsufficientScope(_) => "The access token has insufficient scope",
+            AuthError::InsufficientRole => "Insufficient role",
+            AuthError::InvalidCsrfToken => "Missing or invalid CSRF token",
+            AuthError::MissingValidator => "Token validation is not configured",
+            AuthError::Unavailable => "Token validation is temporarily unavailable",
+        }
+    }
+
+    fn www_authenticate(&self) -> Option<String> {
+        match self {
+            AuthError::MissingToken => Some("Bearer".to_string()),
+            AuthError::InsufficientScope(scope) => Some(format!(
+                "Bearer error=\"insufficient_scope\", error_description=\"{}\", scope=\"{}\"",
+                self.message(),
+                scope
+            )),
+            _ => self.error_code().map(|code| {
+                format!(
+                    "Bearer error=\"{}\", error_description=\"{}\"",
+                    code,
+                    self.message()
+                )
+            }),
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let details = self.error_code().map(|code| json!({ "error": code }));
+        let body = Json(json!({
+            "message": self.message(),
+            "details": details,
+        }));
+
+        let mut response = (self.status(), body).into_response();
+        if let Some(challenge) = self
+            .www_authenticate()
+            .and_then(|challenge| HeaderValue::from_str(&challenge).ok())
+        {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::Value;
+
+    async fn render(error: AuthError) -> (StatusCode, Option<String>, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, challenge, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_has_no_error_code() {
+        let (status, challenge, body) = render(AuthError::MissingToken).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge.as_deref(), Some("Bearer"));
+        assert!(body["details"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_expired_token() {
+        let (status, challenge, body) = render(AuthError::ExpiredToken).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            challenge.as_deref(),
+            Some("Bearer error=\"invalid_token\", error_description=\"The access token expired\"")
+        );
+        assert_eq!(body["message"], "The access token expired");
+        assert_eq!(body["details"]["error"], "invalid_token");
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_scope() {
+        let (status, challenge, body) =
+            render(AuthError::InsufficientScope("tasks:write".to_string())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(challenge.unwrap().ends_with("scope=\"tasks:write\""));
+        assert_eq!(body["details"]["error"], "insufficient_scope");
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_have_no_challenge() {
+        let (status, challenge, _) = render(AuthError::Unavailable).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(challenge.is_none());
+    }
+}
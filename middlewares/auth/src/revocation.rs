@@ -0,0 +1,164 @@
+use crate::cache::{TtlCache, CACHE_CAPACITY};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub type RevocationError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Revoked tokens, keyed by `jti`.
+///
+/// `expires_at` is the `exp` of the revoked token: once it has passed the token is rejected
+/// anyway, so implementations may forget about it.
+#[async_trait]
+pub trait RevocationStore: Send + Sync {
+    async fn revoke(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<(), RevocationError>;
+
+    async fn is_revoked(&self, jti: &str) -> Result<bool, RevocationError>;
+}
+
+/// Store for a single instance and tests. Revocations are lost on restart.
+#[derive(Default)]
+pub struct InMemoryRevocationStore {
+    revoked: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl InMemoryRevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RevocationStore for InMemoryRevocationStore {
+    async fn revoke(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<(), RevocationError> {
+        let mut revoked = self.revoked.lock().unwrap();
+        let now = Utc::now();
+        revoked.retain(|_, expires_at| *expires_at > now);
+        revoked.insert(jti.to_string(), expires_at);
+        Ok(())
+    }
+
+    async fn is_revoked(&self, jti: &str) -> Result<bool, RevocationError> {
+        Ok(self.revoked.lock().unwrap().contains_key(jti))
+    }
+}
+
+/// Remembers answers of a `RevocationStore` for `ttl`, so that a token used on every request
+/// doesn't cost a database round trip each time. A revocation made through another instance
+/// takes effect after at most `ttl`.
+pub struct RevocationCache {
+    store: Arc<dyn RevocationStore>,
+    ttl: Duration,
+    entries: TtlCache<bool>,
+}
+
+impl RevocationCache {
+    pub fn new(store: Arc<dyn RevocationStore>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            entries: TtlCache::new(CACHE_CAPACITY),
+        }
+    }
+
+    pub async fn revoke(
+        &self,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), RevocationError> {
+        self.store.revoke(jti, expires_at).await?;
+        self.insert(jti, true);
+        Ok(())
+    }
+
+    pub async fn is_revoked(&self, jti: &str) -> Result<bool, RevocationError> {
+        if let Some(revoked) = self.entries.get(jti) {
+            return Ok(revoked);
+        }
+
+        let revoked = self.store.is_revoked(jti).await?;
+        self.insert(jti, revoked);
+        Ok(revoked)
+    }
+
+    fn insert(&self, jti: &str, revoked: bool) {
+        self.entries.insert(jti, revoked, Instant::now() + self.ttl);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts lookups that reach the store.
+    #[derive(Default)]
+    struct CountingStore {
+        inner: InMemoryRevocationStore,
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl RevocationStore for CountingStore {
+        async fn revoke(
+            &self,
+            jti: &str,
+            expires_at: DateTime<Utc>,
+        ) -> Result<(), RevocationError> {
+            self.inner.revoke(jti, expires_at).await
+        }
+
+        async fn is_revoked(&self, jti: &str) -> Result<bool, RevocationError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.inner.is_revoked(jti).await
+        }
+    }
+
+    fn in_an_hour() -> DateTime<Utc> {
+        Utc::now() + chrono::Duration::hours(1)
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        let store = InMemoryRevocationStore::new();
+        store.revoke("revoked", in_an_hour()).await.unwrap();
+
+        assert!(store.is_revoked("revoked").await.unwrap());
+        assert!(!store.is_revoked("other").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_forgets_expired_tokens() {
+        let store = InMemoryRevocationStore::new();
+        store
+            .revoke("expired", Utc::now() - chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        store.revoke("revoked", in_an_hour()).await.unwrap();
+
+        assert!(!store.is_revoked("expired").await.unwrap());
+        assert!(store.is_revoked("revoked").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cache_answers_repeated_lookups() {
+        let store = Arc::new(CountingStore::default());
+        let cache = RevocationCache::new(store.clone(), Duration::from_secs(60));
+
+        assert!(!cache.is_revoked("jti").await.unwrap());
+        assert!(!cache.is_revoked("jti").await.unwrap());
+        assert_eq!(store.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_sees_own_revocations_immediately() {
+        let store = Arc::new(CountingStore::default());
+        let cache = RevocationCache::new(store.clone(), Duration::from_secs(60));
+
+        assert!(!cache.is_revoked("jti").await.unwrap());
+        cache.revoke("jti", in_an_hour()).await.unwrap();
+        assert!(cache.is_revoked("jti").await.unwrap());
+    }
+}
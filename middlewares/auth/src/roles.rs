@@ -0,0 +1,115 @@
+use crate::rejection::AuthError;
+use crate::AuthenticatedUser;
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use models::user::{ROLE_ADMIN, ROLE_USER};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// Role checked by `RequireRole`. Services may declare their own roles.
+pub trait Role: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+pub struct User;
+
+impl Role for User {
+    const NAME: &'static str = ROLE_USER;
+}
+
+pub struct Admin;
+
+impl Role for Admin {
+    const NAME: &'static str = ROLE_ADMIN;
+}
+
+/// Authenticated user holding role `R`.
+///
+/// Rejects with 401 when the token is missing or invalid and with 403 when the role is missing.
+pub struct RequireRole<R: Role> {
+    pub user: AuthenticatedUser,
+    role: PhantomData<R>,
+}
+
+impl<R: Role> Deref for RequireRole<R> {
+    type Target = AuthenticatedUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Sync + Send,
+    R: Role,
+{
+    type Rejection = AuthError;
+
+    fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
+        Box::pin(async move {
+            let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+            if !user.has_role(R::NAME) {
+                return Err(AuthError::InsufficientRole);
+            }
+
+            Ok(RequireRole {
+                user,
+                role: PhantomData,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_token_manager, TestToken};
+    use crate::tokens::TokenManager;
+    use axum::http::{Request, StatusCode};
+    use std::sync::Arc;
+
+    fn setup() -> Arc<TokenManager> {
+        Arc::new(test_token_manager())
+    }
+
+    async fn extract(
+        manager: Arc<TokenManager>,
+        token: Option<String>,
+    ) -> Result<RequireRole<Admin>, AuthError> {
+        let mut request = Request::builder().extension(manager);
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        RequireRole::<Admin>::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn test_role_is_granted() {
+        let token = TestToken::new().roles(&[ROLE_USER, ROLE_ADMIN]).build();
+
+        let admin = extract(setup(), Some(token)).await.unwrap();
+        assert!(admin.has_role(ROLE_ADMIN));
+    }
+
+    #[tokio::test]
+    async fn test_missing_role_is_forbidden() {
+        let token = TestToken::new().roles(&[ROLE_USER]).build();
+
+        let err = extract(setup(), Some(token)).await.err().unwrap();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_is_unauthorized() {
+        let err = extract(setup(), None).await.err().unwrap();
+        assert_eq!(err, AuthError::MissingToken);
+    }
+}
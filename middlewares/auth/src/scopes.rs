@@ -0,0 +1,110 @@
+use crate::rejection::AuthError;
+use crate::AuthenticatedUser;
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// Set of OAuth2 scopes checked by `RequireScopes`. Declared by the service next to its routes.
+pub trait Scopes: Send + Sync + 'static {
+    const SCOPES: &'static [&'static str];
+}
+
+/// Authenticated user whose token was granted every scope of `S`.
+///
+/// Rejects with 401 when the token is missing or invalid and with 403 when a scope is missing.
+pub struct RequireScopes<S: Scopes> {
+    pub user: AuthenticatedUser,
+    scopes: PhantomData<S>,
+}
+
+impl<S: Scopes> Deref for RequireScopes<S> {
+    type Target = AuthenticatedUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+#[async_trait]
+impl<St, S> FromRequestParts<St> for RequireScopes<S>
+where
+    St: Sync + Send,
+    S: Scopes,
+{
+    type Rejection = AuthError;
+
+    fn from_request_parts(
+        parts: &mut Parts,
+        state: &St,
+    ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
+        Box::pin(async move {
+            let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+            if !S::SCOPES.iter().all(|scope| user.has_scope(scope)) {
+                return Err(AuthError::InsufficientScope(S::SCOPES.join(" ")));
+            }
+
+            Ok(RequireScopes {
+                user,
+                scopes: PhantomData,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_token_manager, TestToken};
+    use crate::tokens::TokenManager;
+    use axum::http::{Request, StatusCode};
+    use std::sync::Arc;
+
+    struct ReadWrite;
+
+    impl Scopes for ReadWrite {
+        const SCOPES: &'static [&'static str] = &["tasks:read", "tasks:write"];
+    }
+
+    fn setup() -> Arc<TokenManager> {
+        Arc::new(test_token_manager())
+    }
+
+    async fn extract(
+        manager: Arc<TokenManager>,
+        token: Option<String>,
+    ) -> Result<RequireScopes<ReadWrite>, AuthError> {
+        let mut request = Request::builder().extension(manager);
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        RequireScopes::<ReadWrite>::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn test_all_scopes_granted() {
+        let token = TestToken::new()
+            .scopes(&["tasks:read", "tasks:write", "profile"])
+            .build();
+
+        let user = extract(setup(), Some(token)).await.unwrap();
+        assert!(user.has_scope("tasks:write"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_scope_is_forbidden() {
+        let token = TestToken::new().scopes(&["tasks:read"]).build();
+
+        let err = extract(setup(), Some(token)).await.err().unwrap();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_is_unauthorized() {
+        let err = extract(setup(), None).await.err().unwrap();
+        assert_eq!(err, AuthError::MissingToken);
+    }
+}
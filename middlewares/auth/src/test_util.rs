@@ -0,0 +1,263 @@
+//! Tokens and a `TokenManager` for tests of protected handlers. Enabled by the `test-util`
+//! feature, for dev-dependencies only.
+
+use crate::claims::{Claims, Principal, TokenUse};
+use crate::keys::JwtKey;
+use crate::tokens::TokenManager;
+use crate::JWTState;
+use axum::{Extension, Router};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{encode, Header};
+use models::user::User;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub const TEST_SECRET: &[u8] = b"test-secret";
+pub const TEST_ISSUER: &str = "bartender";
+pub const TEST_AUDIENCE: &str = "todo";
+
+/// Subject of tokens built without an explicit user.
+pub const TEST_USER_ID: Uuid = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+
+/// HS256 key shared by every test token and `test_token_manager`.
+pub fn test_key() -> JwtKey {
+    JwtKey::from_secret(TEST_SECRET)
+}
+
+pub fn test_jwt_state() -> JWTState {
+    JWTState {
+        key: test_key(),
+        retired_keys: vec![],
+        issuer: TEST_ISSUER.to_string(),
+        audience: vec![TEST_AUDIENCE.to_string()],
+        leeway: 0,
+        access_token_expiration: 3600,
+        refresh_token_expiration: 604800,
+    }
+}
+
+/// `TokenManager` accepting the tokens of `TestToken`.
+pub fn test_token_manager() -> TokenManager {
+    TokenManager::new(test_jwt_state())
+}
+
+pub fn test_user() -> User {
+    User {
+        id: TEST_USER_ID,
+        username: "alice".to_string(),
+        email: "alice@example.com".to_string(),
+        password_hash: String::new(),
+        roles: vec!["user".to_string()],
+    }
+}
+
+/// Makes `token_manager` available to the extractors of every route, like `AuthLayer` does.
+pub fn with_token_manager<S>(router: Router<S>, token_manager: Arc<TokenManager>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(Extension(token_manager))
+}
+
+/// Builder of signed test tokens. The default is a valid access token of `test_user`
+/// without scopes, expiring in an hour.
+///
+/// ```ignore
+/// let token = TestToken::new().scopes(&["tasks:read"]).build();
+/// let expired = TestToken::expired().build();
+/// ```
+pub struct TestToken {
+    sub: String,
+    roles: Vec<String>,
+    scopes: Vec<String>,
+    audience: Vec<String>,
+    issuer: String,
+    token_use: TokenUse,
+    principal: Principal,
+    session_id: Option<String>,
+    expires_in: Duration,
+}
+
+impl Default for TestToken {
+    fn default() -> Self {
+        let user = test_user();
+        Self {
+            sub: user.id.to_string(),
+            roles: user.roles,
+            scopes: vec![],
+            audience: vec![TEST_AUDIENCE.to_string()],
+            issuer: TEST_ISSUER.to_string(),
+            token_use: TokenUse::Access,
+            principal: Principal::User,
+            session_id: None,
+            expires_in: Duration::hours(1),
+        }
+    }
+}
+
+impl TestToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Token that expired a minute ago.
+    pub fn expired() -> Self {
+        Self::new().expires_in(Duration::minutes(-1))
+    }
+
+    /// Token issued for another service.
+    pub fn wrong_audience() -> Self {
+        Self::new().audience("other-service")
+    }
+
+    /// Refresh token, rejected where an access token is expected.
+    pub fn wrong_type() -> Self {
+        Self::new().token_use(TokenUse::Refresh)
+    }
+
+    /// Token of a service client obtained with the client-credentials grant.
+    pub fn service(client_id: &str) -> Self {
+        Self {
+            sub: client_id.to_string(),
+            roles: vec![],
+            principal: Principal::Service,
+            ..Self::new()
+        }
+    }
+
+    pub fn user(mut self, user: &User) -> Self {
+        self.sub = user.id.to_string();
+        self.roles = user.roles.clone();
+        self
+    }
+
+    pub fn roles(mut self, roles: &[&str]) -> Self {
+        self.roles = roles.iter().map(|role| role.to_string()).collect();
+        self
+    }
+
+    pub fn scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        self
+    }
+
+    pub fn audience(mut self, audience: &str) -> Self {
+        self.audience = vec![audience.to_string()];
+        self
+    }
+
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.issuer = issuer.to_string();
+        self
+    }
+
+    pub fn session(mut self, session_id: &str) -> Self {
+        self.session_id = Some(session_id.to_string());
+        self
+    }
+
+    pub fn token_use(mut self, token_use: TokenUse) -> Self {
+        self.token_use = token_use;
+        self
+    }
+
+    pub fn expires_in(mut self, expires_in: Duration) -> Self {
+        self.expires_in = expires_in;
+        self
+    }
+
+    /// Signs the token with `test_key`. Nothing is validated, so any combination can be built.
+    pub fn build(self) -> String {
+        let now = Utc::now();
+        let issued_at = now.min(now + self.expires_in);
+        let claims = Claims {
+            sub: self.sub,
+            exp: (now + self.expires_in).timestamp() as usize,
+            iat: issued_at.timestamp() as usize,
+            nbf: issued_at.timestamp() as usize,
+            iss: self.issuer,
+            aud: self.audience,
+            jti: Uuid::new_v4().to_string(),
+            token_use: self.token_use,
+            roles: self.roles,
+            scope: self.scopes.join(" "),
+            principal: self.principal,
+            sid: self.session_id,
+        };
+
+        let key = test_key();
+        encode(
+            &Header::new(key.algorithm()),
+            &claims,
+            key.encoding_key().expect("Test key can sign"),
+        )
+        .expect("Failed to sign test token")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AuthenticatedUser;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        let router =
+            Router::new().route("/me", get(|user: AuthenticatedUser| async move { user.id }));
+        with_token_manager(router, Arc::new(test_token_manager()))
+    }
+
+    async fn get_status(token: String) -> StatusCode {
+        let request = Request::builder()
+            .uri("/me")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        router().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_is_accepted() {
+        assert_eq!(get_status(TestToken::new().build()).await, StatusCode::OK);
+        assert_eq!(
+            get_status(TestToken::service("billing").build()).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_tokens_are_rejected() {
+        for token in [
+            TestToken::expired(),
+            TestToken::wrong_audience(),
+            TestToken::wrong_type(),
+        ] {
+            assert_eq!(get_status(token.build()).await, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[test]
+    fn test_claims_of_built_token() {
+        let manager = test_token_manager();
+        let token = TestToken::new()
+            .roles(&["admin"])
+            .scopes(&["tasks:read", "tasks:write"])
+            .build();
+
+        let user = manager.decode_jwt(&token).unwrap();
+        assert_eq!(user.id, TEST_USER_ID.to_string());
+        assert!(user.has_role("admin"));
+        assert!(user.has_scope("tasks:write"));
+
+        let err = manager
+            .decode_jwt(&TestToken::expired().build())
+            .unwrap_err();
+        assert_eq!(
+            *err.kind(),
+            jsonwebtoken::errors::ErrorKind::ExpiredSignature
+        );
+    }
+}
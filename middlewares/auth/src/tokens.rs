@@ -1,13 +1,31 @@
-use crate::claims::Claims;
-use crate::{AuthenticatedUser, JWTState};
-use chrono::{Duration, Utc};
-use jsonwebtoken::errors::Error as JwtError;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use crate::claims::{Claims, TokenUse};
+use crate::jwks::{JwksClient, JwksError};
+use crate::keys::JwtKey;
+use crate::hashing::hash_token;
+use crate::personal_tokens::{PersonalToken, PersonalTokenError, PersonalTokenStore};
+use crate::revocation::{RevocationCache, RevocationError, RevocationStore};
+use crate::users::{UserLookup, UserLookupCache, UserLookupError};
+use crate::{AuthenticatedUser, JWTState, JWTVerifierState};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use models::user::User;
+use std::borrow::Cow;
+use std::sync::Arc;
 
 pub struct TokenManager {
-    encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
+    /// Key new tokens are signed with, `None` for verifiers
+    key: Option<JwtKey>,
+    /// Other keys accepted on validation
+    verification_keys: Vec<JwtKey>,
+    jwks: Option<Arc<JwksClient>>,
+    issuer: String,
+    audience: Vec<String>,
+    leeway: u64,
+    revocations: Option<RevocationCache>,
+    user_lookup: Option<UserLookupCache>,
+    personal_tokens: Option<Arc<dyn PersonalTokenStore>>,
     pub access_token_expiration: u64,
     pub refresh_token_expiration: u64,
 }
@@ -15,58 +33,652 @@ pub struct TokenManager {
 impl TokenManager {
     pub fn new(jwt_state: JWTState) -> Self {
         Self {
-            encoding_key: EncodingKey::from_secret(jwt_state.secret.as_bytes()),
-            decoding_key: DecodingKey::from_secret(jwt_state.secret.as_bytes()),
+            key: Some(jwt_state.key),
+            verification_keys: jwt_state.retired_keys,
+            jwks: None,
+            issuer: jwt_state.issuer,
+            audience: jwt_state.audience,
+            leeway: jwt_state.leeway,
+            revocations: None,
+            user_lookup: None,
+            personal_tokens: None,
             access_token_expiration: jwt_state.access_token_expiration,
             refresh_token_expiration: jwt_state.refresh_token_expiration,
         }
     }
 
-    pub fn get_decoding_key(&self) -> &DecodingKey {
-        &self.decoding_key
+    /// Manager that only verifies tokens. It can't sign any, not even with an HMAC secret.
+    pub fn verifier(state: JWTVerifierState) -> Self {
+        Self {
+            key: None,
+            verification_keys: state.keys.into_iter().map(JwtKey::verify_only).collect(),
+            jwks: None,
+            issuer: state.issuer,
+            audience: state.audience,
+            leeway: state.leeway,
+            revocations: None,
+            user_lookup: None,
+            personal_tokens: None,
+            access_token_expiration: 0,
+            refresh_token_expiration: 0,
+        }
+    }
+
+    /// `iss` of issued tokens.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// `aud` of issued tokens.
+    pub fn audience(&self) -> &[String] {
+        &self.audience
+    }
+
+    /// Also accepts the keys published by bartender at `/.well-known/jwks.json`.
+    pub fn with_jwks(mut self, jwks: Arc<JwksClient>) -> Self {
+        self.jwks = Some(jwks);
+        self
+    }
+
+    /// Fetches the JWKS again when it is stale or lacks the key `token` is signed with, e.g.
+    /// right after bartender rolled its key. Does nothing without a JWKS client.
+    pub async fn refresh_keys(&self, token: &str) -> Result<(), JwksError> {
+        let Some(jwks) = &self.jwks else {
+            return Ok(());
+        };
+        let Ok(header) = decode_header(token) else {
+            return Ok(());
+        };
+        if self.keys().any(|key| key.kid() == header.kid.as_deref()) {
+            return Ok(());
+        }
+        jwks.refresh_if_stale(header.kid.as_deref()).await
+    }
+
+    /// Checks `jti` of validated tokens against `store`, remembering answers for `cache_ttl`.
+    pub fn with_revocation_store(
+        mut self,
+        store: Arc<dyn RevocationStore>,
+        cache_ttl: std::time::Duration,
+    ) -> Self {
+        self.revocations = Some(RevocationCache::new(store, cache_ttl));
+        self
+    }
+
+    /// Whether the token, or the login session it belongs to, was revoked. Always `false`
+    /// without a revocation store.
+    pub async fn is_revoked(&self, claims: &Claims) -> Result<bool, RevocationError> {
+        let Some(revocations) = &self.revocations else {
+            return Ok(false);
+        };
+        if revocations.is_revoked(&claims.jti).await? {
+            return Ok(true);
+        }
+        match &claims.sid {
+            Some(sid) => revocations.is_revoked(&session_key(sid)).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Rejects the token from now on, until it expires.
+    pub async fn revoke(&self, claims: &Claims) -> Result<(), RevocationError> {
+        let revocations = self
+            .revocations
+            .as_ref()
+            .ok_or("Revocation store is not configured")?;
+        let expires_at =
+            DateTime::from_timestamp(claims.exp as i64, 0).ok_or("Invalid expiration")?;
+        revocations.revoke(&claims.jti, expires_at).await
+    }
+
+    /// Rejects every access token of the login session from now on. They all expire within
+    /// `access_token_expiration`, the revocation is kept that long.
+    pub async fn revoke_session(&self, session_id: &str) -> Result<(), RevocationError> {
+        let revocations = self
+            .revocations
+            .as_ref()
+            .ok_or("Revocation store is not configured")?;
+        let expires_at = Utc::now()
+            + Duration::seconds((self.access_token_expiration + self.leeway) as i64);
+        revocations.revoke(&session_key(session_id), expires_at).await
+    }
+
+    /// Checks on authenticated requests that the user still exists and is active, remembering
+    /// answers for `cache_ttl`.
+    pub fn with_user_lookup(
+        mut self,
+        user_lookup: Arc<dyn UserLookup>,
+        cache_ttl: std::time::Duration,
+    ) -> Self {
+        self.user_lookup = Some(UserLookupCache::new(user_lookup, cache_ttl));
+        self
+    }
+
+    /// Whether the user may still use the API. Always `true` without a user lookup.
+    pub async fn is_user_active(&self, user_id: &str) -> Result<bool, UserLookupError> {
+        match &self.user_lookup {
+            Some(user_lookup) => user_lookup.is_active(user_id).await,
+            None => Ok(true),
+        }
+    }
+
+    /// Accepts personal access tokens found in `store` alongside JWTs.
+    pub fn with_personal_token_store(mut self, store: Arc<dyn PersonalTokenStore>) -> Self {
+        self.personal_tokens = Some(store);
+        self
+    }
+
+    /// Unexpired personal access token. Always `None` without a personal token store.
+    pub async fn find_personal_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<PersonalToken>, PersonalTokenError> {
+        let Some(store) = &self.personal_tokens else {
+            return Ok(None);
+        };
+
+        let personal_token = store.find(&hash_token(token)).await?;
+        Ok(personal_token.filter(|personal_token| {
+            personal_token
+                .expires_at
+                .is_none_or(|expires_at| expires_at > Utc::now())
+        }))
     }
 
-    pub fn get_encoding_key(&self) -> &EncodingKey {
-        &self.encoding_key
+    /// Public signing keys for `/.well-known/jwks.json`. Empty for HS256.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys().filter_map(|key| key.jwk().cloned()).collect(),
+        }
+    }
+
+    /// Checks the signature, expiration and that the token was issued for `token_use`.
+    pub fn validate_token(&self, token: &str, token_use: TokenUse) -> Result<Claims, JwtError> {
+        let header = decode_header(token)?;
+        let key = self.find_key(header.kid.as_deref())?;
+
+        let claims = decode::<Claims>(token, key.decoding_key(), &self.validation(&key))?.claims;
+        if claims.token_use != token_use {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+        Ok(claims)
     }
 
-    pub fn validate_token(&self, token: &str) -> Result<Claims, JwtError> {
-        decode::<Claims>(
-            token,
-            &self.decoding_key,
-            &Validation::new(Algorithm::HS256),
+    fn validation(&self, key: &JwtKey) -> Validation {
+        let mut validation = Validation::new(key.algorithm());
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&self.audience);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation
+    }
+
+    fn claims(
+        &self,
+        user: &User,
+        expiration: Duration,
+        token_use: TokenUse,
+        scopes: &[String],
+    ) -> Claims {
+        Claims::from_user(
+            user,
+            expiration,
+            token_use,
+            &self.issuer,
+            &self.audience,
+            scopes,
         )
-        .map(|data| data.claims)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &JwtKey> {
+        self.key.iter().chain(self.verification_keys.iter())
+    }
+
+    fn find_key(&self, kid: Option<&str>) -> Result<Cow<'_, JwtKey>, JwtError> {
+        if let Some(key) = self.keys().find(|key| key.kid() == kid) {
+            return Ok(Cow::Borrowed(key));
+        }
+        self.jwks
+            .as_ref()
+            .and_then(|jwks| jwks.key(kid))
+            .map(Cow::Owned)
+            .ok_or_else(|| ErrorKind::InvalidSignature.into())
+    }
+
+    fn signing_key(&self) -> Result<&JwtKey, JwtError> {
+        self.key
+            .as_ref()
+            .ok_or_else(|| ErrorKind::InvalidKeyFormat.into())
+    }
+
+    fn header(&self) -> Result<Header, JwtError> {
+        let key = self.signing_key()?;
+        Ok(Header {
+            kid: key.kid().map(String::from),
+            ..Header::new(key.algorithm())
+        })
+    }
+
+    fn sign(&self, claims: &Claims) -> Result<String, JwtError> {
+        encode(&self.header()?, claims, self.signing_key()?.encoding_key()?)
+    }
+
+    /// Access token of a service client. Service tokens have no refresh token.
+    pub fn generate_service_token(
+        &self,
+        client_id: &str,
+        scopes: &[String],
+        expiration: Duration,
+    ) -> Result<String, JwtError> {
+        let claims = Claims::from_service(
+            client_id,
+            expiration,
+            &self.issuer,
+            &self.audience,
+            scopes,
+        );
+        self.sign(&claims)
     }
 
     pub fn generate_access_token(
         &self,
         user: &User,
+        scopes: &[String],
+        expiration: Duration,
+    ) -> Result<String, JwtError> {
+        let claims = self.claims(user, expiration, TokenUse::Access, scopes);
+        self.sign(&claims)
+    }
+
+    /// Access token of a login session. Its `sid` claim tells which session a request
+    /// comes from.
+    pub fn generate_session_access_token(
+        &self,
+        user: &User,
+        scopes: &[String],
+        session_id: &str,
         expiration: Duration,
     ) -> Result<String, JwtError> {
         let claims = Claims {
-            sub: user.id.to_string(),
-            exp: (Utc::now() + expiration).timestamp() as usize,
+            sid: Some(session_id.to_string()),
+            ..self.claims(user, expiration, TokenUse::Access, scopes)
         };
-        encode(&Header::default(), &claims, &self.encoding_key)
+        self.sign(&claims)
     }
 
     pub fn generate_refresh_token(
         &self,
         user: &User,
+        scopes: &[String],
         expiration: Duration,
     ) -> Result<String, JwtError> {
-        let claims = Claims {
-            sub: user.id.to_string(),
-            exp: (Utc::now() + expiration).timestamp() as usize,
-        };
-        encode(&Header::default(), &claims, &self.encoding_key)
+        let claims = self.claims(user, expiration, TokenUse::Refresh, scopes);
+        self.sign(&claims)
     }
 
     pub fn decode_jwt(&self, token: &str) -> Result<AuthenticatedUser, JwtError> {
-        let claims = self.validate_token(token)?;
-        Ok(AuthenticatedUser {
-            id: claims.sub,
+        let claims = self.validate_token(token, TokenUse::Access)?;
+        Ok(AuthenticatedUser::from(claims))
+    }
+}
+
+/// Revocation store key of a login session, which can't be mistaken for a `jti`.
+fn session_key(session_id: &str) -> String {
+    format!("sid:{}", session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::revocation::InMemoryRevocationStore;
+    use uuid::Uuid;
+
+    const RSA_PRIVATE: &[u8] = include_bytes!("../testdata/rsa_private.pem");
+    const RSA_PUBLIC: &[u8] = include_bytes!("../testdata/rsa_public.pem");
+    const ED_PRIVATE: &[u8] = include_bytes!("../testdata/ed25519_private.pem");
+    const ED_PUBLIC: &[u8] = include_bytes!("../testdata/ed25519_public.pem");
+
+    fn manager(key: JwtKey) -> TokenManager {
+        manager_for(key, "bartender", &["todo"])
+    }
+
+    fn manager_for(key: JwtKey, issuer: &str, audience: &[&str]) -> TokenManager {
+        TokenManager::new(JWTState {
+            key,
+            retired_keys: vec![],
+            issuer: issuer.to_string(),
+            audience: audience.iter().map(|aud| aud.to_string()).collect(),
+            leeway: 0,
+            access_token_expiration: 3600,
+            refresh_token_expiration: 604800,
         })
     }
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: String::new(),
+            roles: vec!["user".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_hs256_round_trip() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let user = user();
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        let claims = manager.validate_token(&token, TokenUse::Access).unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+        assert!(manager.jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn test_scopes_round_trip() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let scopes = vec!["tasks:read".to_string(), "tasks:write".to_string()];
+        let token = manager
+            .generate_access_token(&user(), &scopes, Duration::seconds(60))
+            .unwrap();
+
+        let claims = manager.validate_token(&token, TokenUse::Access).unwrap();
+        assert_eq!(claims.scope, "tasks:read tasks:write");
+        assert_eq!(manager.decode_jwt(&token).unwrap().scopes, scopes);
+    }
+
+    #[test]
+    fn test_service_token_is_marked() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let token = manager
+            .generate_service_token("billing", &["tasks:read".to_string()], Duration::seconds(60))
+            .unwrap();
+
+        let user = manager.decode_jwt(&token).unwrap();
+        assert_eq!(user.id, "billing");
+        assert!(user.is_service());
+        assert!(user.roles.is_empty());
+    }
+
+    #[test]
+    fn test_session_token_carries_session_id() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let user = user();
+
+        let token = manager
+            .generate_session_access_token(&user, &[], "session-1", Duration::seconds(60))
+            .unwrap();
+        let authenticated = manager.decode_jwt(&token).unwrap();
+        assert_eq!(authenticated.session_id.as_deref(), Some("session-1"));
+
+        let token = manager
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+        assert!(manager.decode_jwt(&token).unwrap().session_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_revoked_session_rejects_its_tokens() {
+        let manager = manager(JwtKey::from_secret(b"secret")).with_revocation_store(
+            Arc::new(InMemoryRevocationStore::new()),
+            std::time::Duration::from_secs(60),
+        );
+        let user = user();
+        let claims = |session_id: &str| {
+            let token = manager
+                .generate_session_access_token(&user, &[], session_id, Duration::seconds(60))
+                .unwrap();
+            manager.validate_token(&token, TokenUse::Access).unwrap()
+        };
+        let (revoked, other) = (claims("session-1"), claims("session-2"));
+
+        manager.revoke_session("session-1").await.unwrap();
+        assert!(manager.is_revoked(&revoked).await.unwrap());
+        assert!(!manager.is_revoked(&other).await.unwrap());
+    }
+
+    #[test]
+    fn test_rs256_verified_with_public_key_only() {
+        let issuer = manager(JwtKey::from_rsa_pem(RSA_PRIVATE).unwrap());
+        let verifier = manager(JwtKey::from_rsa_public_pem(RSA_PUBLIC).unwrap());
+        let user = user();
+        let token = issuer
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        assert_eq!(
+            verifier
+                .validate_token(&token, TokenUse::Access)
+                .unwrap()
+                .sub,
+            user.id.to_string()
+        );
+        assert!(verifier
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .is_err());
+    }
+
+    #[test]
+    fn test_eddsa_verified_with_public_key_only() {
+        let issuer = manager(JwtKey::from_ed_pem(ED_PRIVATE).unwrap());
+        let verifier = manager(JwtKey::from_ed_public_pem(ED_PUBLIC).unwrap());
+        let user = user();
+        let token = issuer
+            .generate_refresh_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        assert_eq!(
+            verifier
+                .validate_token(&token, TokenUse::Refresh)
+                .unwrap()
+                .sub,
+            user.id.to_string()
+        );
+    }
+
+    #[test]
+    fn test_verifier_cannot_sign() {
+        let issuer = manager(JwtKey::from_secret(b"secret"));
+        let verifier = TokenManager::verifier(JWTVerifierState {
+            keys: vec![JwtKey::from_secret(b"secret")],
+            issuer: "bartender".to_string(),
+            audience: vec!["todo".to_string()],
+            leeway: 0,
+        });
+        let user = user();
+        let token = issuer
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        assert_eq!(
+            verifier
+                .validate_token(&token, TokenUse::Access)
+                .unwrap()
+                .sub,
+            user.id.to_string()
+        );
+        assert!(verifier
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .is_err());
+    }
+
+    #[test]
+    fn test_jwks_verifies_issued_tokens() {
+        let issuer = manager(JwtKey::from_rsa_pem(RSA_PRIVATE).unwrap());
+        let jwks = issuer.jwks();
+        assert_eq!(jwks.keys.len(), 1);
+
+        let token = issuer
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+        let decoding_key = jsonwebtoken::DecodingKey::from_jwk(&jwks.keys[0]).unwrap();
+        let mut validation = Validation::new(jsonwebtoken::Algorithm::RS256);
+        validation.set_audience(&["todo"]);
+        assert!(decode::<Claims>(&token, &decoding_key, &validation).is_ok());
+    }
+
+    #[test]
+    fn test_token_signed_with_other_key_is_rejected() {
+        let rsa = manager(JwtKey::from_rsa_pem(RSA_PRIVATE).unwrap());
+        let hmac = manager(JwtKey::from_secret(b"secret"));
+        let token = rsa
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        assert!(hmac.validate_token(&token, TokenUse::Access).is_err());
+    }
+
+    #[test]
+    fn test_retired_key_still_verifies() {
+        let old = JwtKey::from_secret(b"old").with_kid("old");
+        let new = JwtKey::from_ed_pem(ED_PRIVATE).unwrap().with_kid("new");
+        let user = user();
+
+        let before = manager(old.clone())
+            .generate_refresh_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+        let rotated = TokenManager::new(JWTState {
+            key: new,
+            retired_keys: vec![old],
+            issuer: "bartender".to_string(),
+            audience: vec!["todo".to_string()],
+            leeway: 0,
+            access_token_expiration: 3600,
+            refresh_token_expiration: 604800,
+        });
+        let after = rotated
+            .generate_access_token(&user, &[], Duration::seconds(60))
+            .unwrap();
+
+        assert_eq!(decode_header(&after).unwrap().kid.as_deref(), Some("new"));
+        assert!(rotated.validate_token(&before, TokenUse::Refresh).is_ok());
+        assert!(rotated.validate_token(&after, TokenUse::Access).is_ok());
+        assert_eq!(rotated.jwks().keys.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_kid_is_rejected() {
+        let issuer = manager(JwtKey::from_secret(b"secret").with_kid("dropped"));
+        let verifier = manager(JwtKey::from_secret(b"secret").with_kid("current"));
+        let token = issuer
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        assert!(verifier.validate_token(&token, TokenUse::Access).is_err());
+    }
+
+    #[test]
+    fn test_refresh_token_is_not_an_access_token() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let token = manager
+            .generate_refresh_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        assert!(manager.validate_token(&token, TokenUse::Access).is_err());
+        assert!(manager.decode_jwt(&token).is_err());
+        assert!(manager.validate_token(&token, TokenUse::Refresh).is_ok());
+    }
+
+    #[test]
+    fn test_access_token_is_not_a_refresh_token() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let token = manager
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        assert!(manager.validate_token(&token, TokenUse::Refresh).is_err());
+        assert!(manager.validate_token(&token, TokenUse::Access).is_ok());
+    }
+
+    #[test]
+    fn test_registered_claims_are_issued() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let first = manager
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+        let second = manager
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        let claims = manager.validate_token(&first, TokenUse::Access).unwrap();
+        assert_eq!(claims.iss, "bartender");
+        assert_eq!(claims.aud, vec!["todo".to_string()]);
+        assert_eq!(claims.exp - claims.iat, 60);
+        assert!(claims.nbf <= claims.iat);
+
+        let other = manager.validate_token(&second, TokenUse::Access).unwrap();
+        assert_ne!(claims.jti, other.jti);
+    }
+
+    #[test]
+    fn test_token_for_other_audience_is_rejected() {
+        let todo = manager_for(JwtKey::from_secret(b"secret"), "bartender", &["todo"]);
+        let billing = manager_for(JwtKey::from_secret(b"secret"), "bartender", &["billing"]);
+        let both = manager_for(
+            JwtKey::from_secret(b"secret"),
+            "bartender",
+            &["billing", "todo"],
+        );
+        let token = todo
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        let err = billing
+            .validate_token(&token, TokenUse::Access)
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidAudience);
+        assert!(both.validate_token(&token, TokenUse::Access).is_ok());
+    }
+
+    #[test]
+    fn test_token_from_other_issuer_is_rejected() {
+        let issuer = manager_for(JwtKey::from_secret(b"secret"), "someone-else", &["todo"]);
+        let verifier = manager(JwtKey::from_secret(b"secret"));
+        let token = issuer
+            .generate_access_token(&user(), &[], Duration::seconds(60))
+            .unwrap();
+
+        let err = verifier
+            .validate_token(&token, TokenUse::Access)
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidIssuer);
+    }
+
+    #[test]
+    fn test_leeway_allows_clock_skew() {
+        let strict = manager(JwtKey::from_secret(b"secret"));
+        let lenient = TokenManager::new(JWTState {
+            key: JwtKey::from_secret(b"secret"),
+            retired_keys: vec![],
+            issuer: "bartender".to_string(),
+            audience: vec!["todo".to_string()],
+            leeway: 60,
+            access_token_expiration: 3600,
+            refresh_token_expiration: 604800,
+        });
+        let token = strict
+            .generate_access_token(&user(), &[], Duration::seconds(-10))
+            .unwrap();
+
+        let err = strict.validate_token(&token, TokenUse::Access).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ExpiredSignature);
+        assert!(lenient.validate_token(&token, TokenUse::Access).is_ok());
+    }
+
+    #[test]
+    fn test_token_not_yet_valid_is_rejected() {
+        let manager = manager(JwtKey::from_secret(b"secret"));
+        let mut claims = manager.claims(&user(), Duration::seconds(3600), TokenUse::Access, &[]);
+        claims.nbf += 600;
+        let token = manager.sign(&claims).unwrap();
+
+        let err = manager
+            .validate_token(&token, TokenUse::Access)
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ImmatureSignature);
+    }
 }
@@ -0,0 +1,75 @@
+use crate::cache::{TtlCache, CACHE_CAPACITY};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub type UserLookupError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Confirms that the subject of a valid token may still use the API.
+///
+/// Tokens stay valid until they expire, so without a lookup a deleted or disabled user keeps
+/// access until then.
+#[async_trait]
+pub trait UserLookup: Send + Sync {
+    /// Whether the user with this id exists and is active.
+    async fn is_active(&self, user_id: &str) -> Result<bool, UserLookupError>;
+}
+
+/// Remembers answers of a `UserLookup` for `ttl`, so that every request of a user doesn't
+/// cost a database round trip. A disabled user keeps access for at most `ttl`.
+pub struct UserLookupCache {
+    lookup: Arc<dyn UserLookup>,
+    ttl: Duration,
+    entries: TtlCache<bool>,
+}
+
+impl UserLookupCache {
+    pub fn new(lookup: Arc<dyn UserLookup>, ttl: Duration) -> Self {
+        Self {
+            lookup,
+            ttl,
+            entries: TtlCache::new(CACHE_CAPACITY),
+        }
+    }
+
+    pub async fn is_active(&self, user_id: &str) -> Result<bool, UserLookupError> {
+        if let Some(active) = self.entries.get(user_id) {
+            return Ok(active);
+        }
+
+        let active = self.lookup.is_active(user_id).await?;
+        self.entries
+            .insert(user_id, active, Instant::now() + self.ttl);
+        Ok(active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Active users only, counting lookups.
+    #[derive(Default)]
+    struct CountingLookup {
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl UserLookup for CountingLookup {
+        async fn is_active(&self, _user_id: &str) -> Result<bool, UserLookupError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_answers_repeated_lookups() {
+        let lookup = Arc::new(CountingLookup::default());
+        let cache = UserLookupCache::new(lookup.clone(), Duration::from_secs(60));
+
+        assert!(cache.is_active("user-1").await.unwrap());
+        assert!(cache.is_active("user-1").await.unwrap());
+        assert_eq!(lookup.lookups.load(Ordering::SeqCst), 1);
+    }
+}
@@ -17,6 +17,7 @@ pub struct Task {
 }
 
 impl Task {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(args: &ArgMatches) {
         let mut tasks = Task::load_tasks();
 
@@ -1 +1 @@
-pub const FILE_NAME: &'static str = "todo_tasks.json";
+pub const FILE_NAME: &str = "todo_tasks.json";
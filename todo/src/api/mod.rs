@@ -1,4 +1,5 @@
 use crate::app::AppState;
+use auth::layer::AuthLayer;
 use axum::Router;
 use std::sync::Arc;
 use utoipa::OpenApi;
@@ -21,11 +22,57 @@ pub mod task;
 )]
 struct ApiDoc;
 
-pub fn create_router(app_state: Arc<AppState>) -> Router {
+pub fn create_router(app_state: Arc<AppState>, auth_layer: AuthLayer) -> Router {
     let api_router = Router::new().nest("/api/task", task::router());
 
     Router::new()
         .merge(Scalar::with_url("/docs", ApiDoc::openapi()))
         .merge(api_router)
+        .layer(auth_layer.public_path("/docs"))
         .layer(axum::Extension(app_state))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::test_util::{test_token_manager, TestToken};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        let pool = PgPool::connect_lazy("postgres://localhost/todo").unwrap();
+        let auth_layer = AuthLayer::new(Arc::new(test_token_manager()));
+        create_router(Arc::new(AppState::new(pool)), auth_layer)
+    }
+
+    async fn delete_status(token: Option<String>) -> StatusCode {
+        let mut request = Request::builder().method("DELETE").uri("/api/task/1");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let response = router()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn test_task_routes_require_token() {
+        assert_eq!(delete_status(None).await, StatusCode::UNAUTHORIZED);
+        for token in [TestToken::expired(), TestToken::wrong_audience()] {
+            assert_eq!(
+                delete_status(Some(token.build())).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_requires_write_scope() {
+        let token = TestToken::new().scopes(&["tasks:read"]).build();
+        assert_eq!(delete_status(Some(token)).await, StatusCode::FORBIDDEN);
+    }
+}
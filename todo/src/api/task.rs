@@ -1,6 +1,7 @@
 use crate::app::AppState;
 use crate::errors::ErrorResponse;
 use crate::models::task::{Task, TaskModel, TaskRequest, TaskResponse};
+use auth::scopes::{RequireScopes, Scopes};
 use axum::{
     extract::{Extension, Json, Path},
     http::StatusCode,
@@ -9,6 +10,20 @@ use axum::{
 };
 use std::sync::Arc;
 
+/// Listing and viewing tasks
+pub struct TasksRead;
+
+impl Scopes for TasksRead {
+    const SCOPES: &'static [&'static str] = &["tasks:read"];
+}
+
+/// Creating, updating and deleting tasks
+pub struct TasksWrite;
+
+impl Scopes for TasksWrite {
+    const SCOPES: &'static [&'static str] = &["tasks:write"];
+}
+
 #[utoipa::path(
     post,
     path = "/api/task",
@@ -16,10 +31,13 @@ use std::sync::Arc;
     responses(
         (status = 201, description = "Task created successfully"),
         (status = 400, description = "Invalid data", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Insufficient scope"),
         (status = 500, description = "Internal server error")
     )
 )]
 pub async fn create_task(
+    _: RequireScopes<TasksWrite>,
     Extension(state): Extension<Arc<AppState>>,
     Json(payload): Json<TaskRequest>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
@@ -50,10 +68,13 @@ pub async fn create_task(
     path = "/api/task/list",
     responses(
         (status = 200, description = "List of tasks retrieved successfully", body = [TaskResponse]),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Insufficient scope"),
         (status = 500, description = "Internal server error")
     )
 )]
 pub async fn get_tasks(
+    _: RequireScopes<TasksRead>,
     Extension(state): Extension<Arc<AppState>>,
 ) -> Result<Json<Vec<TaskResponse>>, (StatusCode, Json<ErrorResponse>)> {
     match state.task_repository.list().await {
@@ -76,6 +97,8 @@ pub async fn get_tasks(
     responses(
         (status = 200, description = "List of tasks retrieved successfully", body = [TaskResponse]),
         (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Insufficient scope"),
         (status = 500, description = "Internal server error")
     ),
     params(
@@ -83,6 +106,7 @@ pub async fn get_tasks(
     )
 )]
 pub async fn get_task(
+    _: RequireScopes<TasksRead>,
     Path(id): Path<i32>,
     Extension(state): Extension<Arc<AppState>>,
 ) -> Result<Json<TaskResponse>, (StatusCode, Json<ErrorResponse>)> {
@@ -111,6 +135,8 @@ pub async fn get_task(
         (status = 200, description = "Task updated successfully", body = TaskResponse),
         (status = 400, description = "Validation error", body = ErrorResponse),
         (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Insufficient scope"),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     params(
@@ -118,6 +144,7 @@ pub async fn get_task(
     )
 )]
 pub async fn update_task(
+    _: RequireScopes<TasksWrite>,
     Extension(state): Extension<Arc<AppState>>,
     Path(id): Path<i32>,
     Json(payload): Json<TaskRequest>,
@@ -160,6 +187,8 @@ pub async fn update_task(
     responses(
         (status = 200, description = "Task deleted successfully"),
         (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Insufficient scope"),
         (status = 500, description = "Internal server error")
     ),
     params(
@@ -167,6 +196,7 @@ pub async fn update_task(
     )
 )]
 pub async fn delete_task(
+    _: RequireScopes<TasksWrite>,
     Extension(state): Extension<Arc<AppState>>,
     Path(id): Path<i32>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
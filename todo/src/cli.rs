@@ -33,14 +33,14 @@ mod tests {
 
     #[test]
     fn test_default_arguments() {
-        let args = Cli::try_parse_from(&["test-app"]).unwrap();
+        let args = Cli::try_parse_from(["test-app"]).unwrap();
         assert_eq!(args.config, PathBuf::from("todo.config.yaml"));
         assert_eq!(args.log_level, LogLevel::Info);
     }
 
     #[test]
     fn test_custom_arguments() {
-        let args = Cli::try_parse_from(&[
+        let args = Cli::try_parse_from([
             "test-app",
             "--config", "custom_config.yaml",
             "--log-level", "debug"
@@ -1,19 +1,156 @@
 use anyhow::anyhow;
+use auth::keys::JwtKey;
+use jsonwebtoken::Algorithm;
 use multitool_hg::database::config::DatabaseConfig;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppConfig {
     pub host: String,
     pub port: String,
+    /// Bartender introspection endpoint. When set tokens are validated remotely and
+    /// no key material is needed
+    pub introspection_url: Option<String>,
+    /// Service client todo authenticates to `introspection_url` with, see `bartender create-client`
+    pub introspection_client_id: Option<String>,
+    pub introspection_client_secret: Option<String>,
+    /// Bartender JWKS endpoint. When set RS256 and EdDSA keys are fetched from it, and
+    /// `jwt_public_key` is not needed
+    pub jwks_url: Option<String>,
+    /// Seconds the fetched keys are used before they are fetched again
+    #[serde(default = "default_jwks_max_age")]
+    pub jwks_max_age: u64,
+    /// Algorithm of the bartender signing key: HS256 uses `jwt_secret`, RS256 and EdDSA use `jwt_public_key`
+    #[serde(default)]
+    pub jwt_algorithm: Algorithm,
+    #[serde(default)]
+    pub jwt_secret: String,
+    /// Path to the PEM encoded public key of bartender
+    pub jwt_public_key: Option<PathBuf>,
+    /// `kid` of the bartender signing key, if it has one
+    pub jwt_key_id: Option<String>,
+    /// Keys bartender retired with `rotate-key`, accepted until the tokens they signed expire
+    #[serde(default)]
+    pub jwt_retired_keys: Vec<RetiredKeyConfig>,
+    /// Accepted `iss` claim
+    #[serde(default = "default_jwt_issuer")]
+    pub jwt_issuer: String,
+    /// Names of this service in the `aud` claim
+    #[serde(default = "default_jwt_audience")]
+    pub jwt_audience: Vec<String>,
+    /// Allowed clock skew in seconds when checking `exp` and `nbf`
+    #[serde(default = "default_jwt_leeway")]
+    pub jwt_leeway: u64,
+    /// Seconds a revocation lookup is cached for
+    #[serde(default = "default_revocation_cache_ttl")]
+    pub revocation_cache_ttl: u64,
+    /// Check in `revocation_database` on requests that the user still exists and is active
+    #[serde(default)]
+    pub check_active_users: bool,
+    /// Seconds the result of an active user check is cached for
+    #[serde(default = "default_active_user_cache_ttl")]
+    pub active_user_cache_ttl: u64,
+}
+
+fn default_jwks_max_age() -> u64 {
+    300
+}
+
+fn default_jwt_issuer() -> String {
+    "bartender".to_string()
+}
+
+fn default_jwt_audience() -> Vec<String> {
+    vec!["todo".to_string()]
+}
+
+fn default_jwt_leeway() -> u64 {
+    60
+}
+
+fn default_revocation_cache_ttl() -> u64 {
+    30
+}
+
+fn default_active_user_cache_ttl() -> u64 {
+    30
+}
+
+/// Verification key bartender signed with before a rotation.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RetiredKeyConfig {
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub secret: String,
+    pub public_key: Option<PathBuf>,
+}
+
+impl AppConfig {
+    /// Verification keys of the config. None are needed when they come from `jwks_url`.
+    pub(crate) fn jwt_keys(&self) -> anyhow::Result<Vec<JwtKey>> {
+        let mut keys = vec![];
+        if self.jwks_url.is_none() || !self.jwt_secret.is_empty() || self.jwt_public_key.is_some()
+        {
+            keys.push(load_jwt_key(
+                self.jwt_key_id.as_deref(),
+                self.jwt_algorithm,
+                &self.jwt_secret,
+                self.jwt_public_key.as_deref(),
+            )?);
+        }
+        for retired in &self.jwt_retired_keys {
+            keys.push(load_jwt_key(
+                retired.kid.as_deref(),
+                retired.algorithm,
+                &retired.secret,
+                retired.public_key.as_deref(),
+            )?);
+        }
+        Ok(keys)
+    }
+}
+
+/// Verification key for tokens issued by bartender.
+fn load_jwt_key(
+    kid: Option<&str>,
+    algorithm: Algorithm,
+    secret: &str,
+    public_key: Option<&Path>,
+) -> anyhow::Result<JwtKey> {
+    let key = if algorithm == Algorithm::HS256 {
+        if secret.is_empty() {
+            return Err(anyhow!("jwt_secret is required for HS256"));
+        }
+        JwtKey::from_secret(secret.as_bytes())
+    } else {
+        let path =
+            public_key.ok_or_else(|| anyhow!("jwt_public_key is required for {:?}", algorithm))?;
+        let pem = std::fs::read(path).map_err(|err| anyhow!("Can't read {:?}: {}", path, err))?;
+        let key = match algorithm {
+            Algorithm::RS256 => JwtKey::from_rsa_public_pem(&pem),
+            Algorithm::EdDSA => JwtKey::from_ed_public_pem(&pem),
+            algorithm => return Err(anyhow!("Unsupported jwt_algorithm {:?}", algorithm)),
+        };
+        key.map_err(|err| anyhow!("Invalid public key {:?}: {}", path, err))?
+    };
+    Ok(match kid {
+        Some(kid) => key.with_kid(kid),
+        None => key,
+    })
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TodoConfig {
     pub database: DatabaseConfig,
+    /// Bartender database holding revoked tokens, users and personal access tokens. Revocations
+    /// aren't checked and personal access tokens are rejected when omitted. Not needed with
+    /// `introspection_url`, bartender checks them itself
+    pub revocation_database: Option<DatabaseConfig>,
     pub app: AppConfig,
 }
 
@@ -1,12 +1,23 @@
 use std::path::Path;
 use std::process;
 use std::sync::Arc;
+use std::time::Duration;
+use anyhow::anyhow;
+use auth::introspection::IntrospectionClient;
+use auth::jwks::JwksClient;
+use auth::layer::AuthLayer;
+use auth::tokens::TokenManager;
+use auth::{IntrospectionState, JWTVerifierState};
 use log::warn;
+use multitool_hg::database::config::DatabaseConfig;
 use multitool_hg::database::postgres::new_postgres_pool;
 use multitool_hg::logger::tracer_logger::new_tracer_logger;
 use tokio::signal;
 use crate::app::AppState;
 use crate::cli::Cli;
+use ::repository::auth::AuthRepository;
+use ::repository::personal_tokens::PersonalTokenRepository;
+use ::repository::revocation::RevocationRepository;
 
 mod cli;
 mod config;
@@ -32,9 +43,10 @@ async fn run() -> anyhow::Result<()> {
 
     let database_pool = new_postgres_pool(config.database).await.expect("Failed to create Postgres pool");
     let app_state = Arc::new(AppState::new(database_pool));
-
-    let app = api::create_router(app_state);
     let address = format!("{}:{}", config.app.host, config.app.port);
+    let auth_layer = new_auth_layer(config.app, config.revocation_database).await?;
+
+    let app = api::create_router(app_state, auth_layer);
     let listener = tokio::net::TcpListener::bind(address).await.expect("Failed to bind");
     let server = async {
         axum::serve(listener, app).await.expect("Failed to run server");
@@ -55,4 +67,59 @@ async fn run() -> anyhow::Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Tokens are validated by bartender when `introspection_url` is set, locally otherwise.
+async fn new_auth_layer(app: config::AppConfig, revocation_database: Option<DatabaseConfig>) -> anyhow::Result<AuthLayer> {
+    if let Some(introspection_url) = app.introspection_url {
+        let (Some(client_id), Some(client_secret)) = (app.introspection_client_id, app.introspection_client_secret) else {
+            return Err(anyhow!("introspection_client_id and introspection_client_secret are required with introspection_url"));
+        };
+        return Ok(AuthLayer::remote(Arc::new(IntrospectionClient::new(IntrospectionState {
+            url: introspection_url,
+            client_id,
+            client_secret,
+            issuer: app.jwt_issuer,
+            audience: app.jwt_audience,
+        }))));
+    }
+
+    let mut token_manager = TokenManager::verifier(JWTVerifierState {
+        keys: app.jwt_keys()?,
+        issuer: app.jwt_issuer,
+        audience: app.jwt_audience,
+        leeway: app.jwt_leeway,
+    });
+    if let Some(jwks_url) = app.jwks_url {
+        let jwks = Arc::new(JwksClient::new(jwks_url, Duration::from_secs(app.jwks_max_age)));
+        if let Err(err) = jwks.refresh().await {
+            warn!("Failed to fetch JWKS, retrying on the first request: {}", err);
+        }
+        token_manager = token_manager.with_jwks(jwks);
+    }
+    match revocation_database {
+        Some(revocation_database) => {
+            let revocation_pool = Arc::new(new_postgres_pool(revocation_database).await.expect("Failed to create revocation Postgres pool"));
+            token_manager = token_manager
+                .with_revocation_store(
+                    Arc::new(RevocationRepository::new(revocation_pool.clone())),
+                    Duration::from_secs(app.revocation_cache_ttl),
+                )
+                .with_personal_token_store(Arc::new(PersonalTokenRepository::new(revocation_pool.clone())));
+            if app.check_active_users {
+                token_manager = token_manager.with_user_lookup(
+                    Arc::new(AuthRepository::new(revocation_pool)),
+                    Duration::from_secs(app.active_user_cache_ttl),
+                );
+            }
+        }
+        None => {
+            warn!("revocation_database is not configured, revoked tokens are accepted until they expire and personal access tokens are rejected");
+            if app.check_active_users {
+                warn!("check_active_users needs revocation_database, users are not checked");
+            }
+        }
+    }
+
+    Ok(AuthLayer::new(Arc::new(token_manager)))
+}
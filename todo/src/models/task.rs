@@ -131,7 +131,7 @@ impl TaskRequest {
     fn datetime(timestamp: i64) -> DateTime<Utc> {
         Utc.timestamp_opt(timestamp, 0)
             .single()
-            .unwrap_or_else(|| Utc::now())
+            .unwrap_or_else(Utc::now)
     }
 }
 